@@ -1,18 +1,84 @@
 use anyhow::*;
-use fs_extra::copy_items;
-use fs_extra::dir::CopyOptions;
-use std::env;
+use std::{env, path::Path};
+
+// Shared with the renderer, so the checked layouts are the ones actually used and shaders
+// are validated the same way as when they are hot reloaded
+#[path = "src/bind_group_layouts.rs"]
+mod bind_group_layouts;
+#[path = "src/shader_checks.rs"]
+mod shader_checks;
+#[path = "src/vertex_layouts.rs"]
+mod vertex_layouts;
 
 fn main() -> Result<()> {
+    validate_shaders()?;
+    embed_resources()?;
+    Ok(())
+}
+
+/// Writes a table of every file in res/ with its contents for `resources.rs` to include.
+/// These are the defaults used when neither a resource pack nor the asset directory has
+/// a file, so the binary also runs away from the machine that built it.
+fn embed_resources() -> Result<()> {
     // This tells cargo to rerun this script if something in /res/ changes.
-    println!("cargo:rerun-if-changed=res/*");
+    println!("cargo:rerun-if-changed=res");
+
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR")?;
+    let mut table = String::from("&[\n");
+    for path in glob::glob("res/**/*")? {
+        let path = path?;
+        // Blender files are where models come from, the game never loads them
+        if !path.is_file()
+            || path
+                .extension()
+                .is_some_and(|extension| extension == "blend")
+        {
+            continue;
+        }
+        // Assets are named by their path below res/ with forward slashes on every platform
+        let name = path
+            .strip_prefix("res")?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let absolute = Path::new(&manifest_dir).join(&path);
+        table += &format!("    ({:?}, include_bytes!({:?})),\n", name, absolute);
+    }
+    table += "]\n";
 
     let out_dir = env::var("OUT_DIR")?;
-    let mut copy_options = CopyOptions::new();
-    copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    std::fs::write(Path::new(&out_dir).join("embedded_res.rs"), table)?;
+    Ok(())
+}
+
+/// Parses and validates every WGSL file in src/ with naga and checks the vertex shader
+/// inputs against the attributes in `vertex_layouts` and the resource bindings against
+/// `bind_group_layouts`, so shader mistakes fail the build instead of panicking when the
+/// pipelines are created.
+fn validate_shaders() -> Result<()> {
+    println!("cargo:rerun-if-changed=src/bind_group_layouts.rs");
+    println!("cargo:rerun-if-changed=src/shader_checks.rs");
+    println!("cargo:rerun-if-changed=src/vertex_layouts.rs");
+
+    let mut errors = Vec::new();
+    for path in glob::glob("src/*.wgsl")? {
+        let path = path?;
+        println!("cargo:rerun-if-changed={}", path.display());
+        let source = std::fs::read_to_string(&path)?;
+        let file_name = path.file_name().unwrap().to_string_lossy();
+
+        if let Err(e) = shader_checks::validate(&path.to_string_lossy(), &file_name, &source) {
+            errors.push(e);
+        }
+    }
 
+    if !errors.is_empty() {
+        for error in &errors {
+            // Cargo only shows the output of failed build scripts
+            eprintln!("{}", error);
+        }
+        bail!("{} shader(s) failed to validate", errors.len());
+    }
     Ok(())
 }
@@ -0,0 +1,184 @@
+//! Animated block textures such as water. All frames of an animation are stacked from
+//! the top down in one strip image. Only the first frame is packed into the atlas, and
+//! its tile is overwritten with the current frame whenever that changes.
+
+use anyhow::*;
+use image::RgbaImage;
+use std::time::Duration;
+
+// Blending between two frames moves on in steps this fine, so an interpolated texture is
+// uploaded a few times per frame of the animation instead of every time it is drawn
+const INTERPOLATION_STEPS: f32 = 16.0;
+
+/// How the frames of a strip are played back.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureAnimation {
+    // Number of frames in the strip, at least one
+    frames: u32,
+    // Seconds every frame is shown for, more than zero
+    frame_duration: f32,
+    // Fade smoothly into the next frame instead of switching at once
+    interpolate: bool,
+}
+
+impl TextureAnimation {
+    /// Fails without frames or if the frames are not shown for a positive number of
+    /// seconds, neither could be played back.
+    pub fn new(frames: u32, frame_duration: f32, interpolate: bool) -> Result<Self> {
+        ensure!(frames > 0, "An animation needs at least one frame");
+        ensure!(
+            frame_duration.is_finite() && frame_duration > 0.0,
+            "Frames cannot be shown for {} seconds",
+            frame_duration
+        );
+        Ok(Self {
+            frames,
+            frame_duration,
+            interpolate,
+        })
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn frame_duration(&self) -> f32 {
+        self.frame_duration
+    }
+
+    pub fn interpolate(&self) -> bool {
+        self.interpolate
+    }
+
+    /// Seconds until the animation starts over.
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 * self.frame_duration
+    }
+
+    /// The frame shown `time` seconds into the animation, the one after it and how far
+    /// it has faded into that one, which is always 0 without interpolation. Loops forever.
+    pub fn frame_at(&self, time: f32) -> (usize, usize, f32) {
+        let frames = self.frames as usize;
+        let position = (time / self.frame_duration).rem_euclid(self.frames as f32);
+        let frame = (position.floor() as usize).min(frames - 1);
+        let blend = if self.interpolate {
+            (position.fract() * INTERPOLATION_STEPS).floor() / INTERPOLATION_STEPS
+        } else {
+            0.0
+        };
+        (frame, (frame + 1) % frames, blend)
+    }
+
+    /// Cuts a strip into its frames, the strip has to be exactly `frames` frames tall.
+    pub fn split_strip(&self, strip: &RgbaImage) -> Result<Vec<RgbaImage>> {
+        let (width, height) = strip.dimensions();
+        ensure!(
+            height % self.frames == 0,
+            "A {}x{} strip cannot be split into {} frames",
+            width,
+            height,
+            self.frames
+        );
+        let frame_height = height / self.frames;
+        Ok((0..self.frames)
+            .map(|frame| {
+                image::imageops::crop_imm(strip, 0, frame * frame_height, width, frame_height)
+                    .to_image()
+            })
+            .collect())
+    }
+}
+
+struct AnimatedTexture {
+    name: String,
+    animation: TextureAnimation,
+    frames: Vec<RgbaImage>,
+    // Seconds into the animation, wrapped around so it never loses precision
+    time: f32,
+    // Frames and blend the atlas tile holds right now
+    shown: (usize, usize, f32),
+}
+
+impl AnimatedTexture {
+    fn image(&self, (frame, next, blend): (usize, usize, f32)) -> RgbaImage {
+        if blend == 0.0 {
+            return self.frames[frame].clone();
+        }
+        let (a, b) = (&self.frames[frame], &self.frames[next]);
+        RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+            let (a, b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+            image::Rgba(std::array::from_fn(|channel| {
+                (a[channel] as f32 + (b[channel] as f32 - a[channel] as f32) * blend).round() as u8
+            }))
+        })
+    }
+}
+
+/// Every animated texture of the block atlas, all advanced by the same frame time.
+#[derive(Default)]
+pub struct TextureAnimations {
+    textures: Vec<AnimatedTexture>,
+}
+
+impl TextureAnimations {
+    /// Starts animating the texture `name` from its first frame, which has to be what
+    /// its atlas tile holds at the moment. Fails unless there are as many frames as the
+    /// animation plays, all of the same size.
+    pub fn add(
+        &mut self,
+        name: &str,
+        animation: TextureAnimation,
+        frames: Vec<RgbaImage>,
+    ) -> Result<()> {
+        ensure!(
+            frames.len() == animation.frames() as usize,
+            "{} has {} frames but its animation plays {}",
+            name,
+            frames.len(),
+            animation.frames()
+        );
+        ensure!(
+            frames
+                .iter()
+                .all(|f| f.dimensions() == frames[0].dimensions()),
+            "The frames of {} differ in size",
+            name
+        );
+        let shown = animation.frame_at(0.0);
+        self.textures.push(AnimatedTexture {
+            name: name.to_string(),
+            animation,
+            frames,
+            time: 0.0,
+            shown,
+        });
+        Ok(())
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        for texture in &mut self.textures {
+            texture.time =
+                (texture.time + dt.as_secs_f32()).rem_euclid(texture.animation.duration());
+        }
+    }
+
+    /// Frames and blend that `name` is at, as returned by [`TextureAnimation::frame_at`].
+    pub fn frame(&self, name: &str) -> Option<(usize, usize, f32)> {
+        let texture = self.textures.iter().find(|texture| texture.name == name)?;
+        Some(texture.animation.frame_at(texture.time))
+    }
+
+    /// The name and current image of every texture that looks different from the last
+    /// time it was returned, or from its first frame.
+    pub fn take_changed(&mut self) -> Vec<(&str, RgbaImage)> {
+        let mut changed = Vec::new();
+        for texture in &mut self.textures {
+            let frame = texture.animation.frame_at(texture.time);
+            if frame != texture.shown {
+                texture.shown = frame;
+                changed.push((texture.name.as_str(), texture.image(frame)));
+            }
+        }
+        changed
+    }
+}
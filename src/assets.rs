@@ -0,0 +1,371 @@
+//! Files loaded once and shared by everything that uses them. Loading a path that is
+//! already loaded hands out another reference to the same asset, and every reference has
+//! to be released again. The asset and the GPU resources it owns are freed with the last
+//! one. Loads can also run in the background on the loader thread, their assets show up
+//! once [`Assets::update`] picks them up.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    panic::AssertUnwindSafe,
+    sync::{mpsc, Arc, Mutex, OnceLock, Weak},
+};
+
+use crate::texture::Texture;
+
+/// Identifies an asset in [`Assets`]. Handles are never reused, so the handle of a freed
+/// asset stays invalid even after the same path is loaded again.
+pub struct Handle<T> {
+    id: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+// Derives would require `T` to implement the traits as well
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl<T> PartialOrd for Handle<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Handle<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    // The error of the load, the handle keeps it until it is released
+    Failed(String),
+    // Released, or a handle of other assets
+    Unknown,
+}
+
+enum Entry<T> {
+    Loading,
+    Loaded(T),
+    Failed(String),
+}
+
+struct Slot<T> {
+    path: String,
+    references: usize,
+    entry: Entry<T>,
+}
+
+type Finished<T> = (u64, anyhow::Result<T>);
+
+// A load for the loader thread, with the runtime async loads are driven by
+type Job = Box<dyn FnOnce(&tokio::runtime::Runtime) + Send>;
+
+/// Queues `job` for the one thread all background loads of every [`Assets`] run on, which
+/// is started by the first of them. Loads run one after the other in the order they came.
+fn spawn_load(job: Job) {
+    static LOADER: OnceLock<Mutex<mpsc::Sender<Job>>> = OnceLock::new();
+    let loader = LOADER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("asset loader".to_string())
+            .spawn(move || {
+                // Loaders only await other loads, never real I/O, so one thread is enough
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .expect("Could not create the runtime of the asset loader");
+                for job in receiver {
+                    job(&runtime);
+                }
+            })
+            .expect("Could not start the asset loader thread");
+        Mutex::new(sender)
+    });
+    loader
+        .lock()
+        .unwrap()
+        .send(job)
+        .expect("The asset loader thread stopped");
+}
+
+/// Assets of one kind by path.
+pub struct Assets<T> {
+    slots: HashMap<u64, Slot<T>>,
+    ids: HashMap<String, u64>,
+    next_id: u64,
+    // The loader thread sends what it loaded back over this channel
+    sender: mpsc::Sender<Finished<T>>,
+    receiver: mpsc::Receiver<Finished<T>>,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            slots: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl<T: Send + 'static> Assets<T> {
+    /// The handle `path` was loaded with, without adding a reference.
+    pub fn handle(&self, path: &str) -> Option<Handle<T>> {
+        self.ids.get(path).map(|&id| Handle {
+            id,
+            marker: PhantomData,
+        })
+    }
+
+    /// Adds `asset` as what `path` contains with one reference. An earlier load of the same
+    /// path gets another reference instead, and `asset` is dropped.
+    pub fn insert(&mut self, path: &str, asset: T) -> Handle<T> {
+        self.add(path, Entry::Loaded(asset))
+    }
+
+    /// Adds a reference to `path`, and starts loading it on the loader thread with `load`
+    /// if it is not loaded or loading yet.
+    pub fn load_with(
+        &mut self,
+        path: &str,
+        load: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+    ) -> Handle<T> {
+        self.load_on_loader(path, |_| load())
+    }
+
+    /// Like [`Assets::load_with`], for loads that are futures. `load` is only created
+    /// once the loader thread gets to it.
+    pub fn load_async_with<F>(
+        &mut self,
+        path: &str,
+        load: impl FnOnce() -> F + Send + 'static,
+    ) -> Handle<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        self.load_on_loader(path, |runtime| runtime.block_on(load()))
+    }
+
+    fn load_on_loader(
+        &mut self,
+        path: &str,
+        load: impl FnOnce(&tokio::runtime::Runtime) -> anyhow::Result<T> + Send + 'static,
+    ) -> Handle<T> {
+        if let Some(handle) = self.handle(path) {
+            self.retain(handle);
+            return handle;
+        }
+        let handle = self.add(path, Entry::Loading);
+        let sender = self.sender.clone();
+        spawn_load(Box::new(move |runtime| {
+            // A panicking loader must neither leave the asset loading forever nor stop
+            // the loads queued after it
+            let asset = std::panic::catch_unwind(AssertUnwindSafe(|| load(runtime)))
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("The loader panicked")));
+            // The assets may be gone already, then nobody needs this one any more
+            let _ = sender.send((handle.id, asset));
+        }));
+        handle
+    }
+
+    fn add(&mut self, path: &str, entry: Entry<T>) -> Handle<T> {
+        if let Some(handle) = self.handle(path) {
+            self.retain(handle);
+            return handle;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.slots.insert(
+            id,
+            Slot {
+                path: path.to_string(),
+                references: 1,
+                entry,
+            },
+        );
+        self.ids.insert(path.to_string(), id);
+        Handle {
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    /// Stores the assets that finished loading in the background since the last call.
+    /// Failed loads are logged and keep their error.
+    pub fn update(&mut self) {
+        while let Ok(finished) = self.receiver.try_recv() {
+            self.finish(finished);
+        }
+    }
+
+    /// Blocks until `handle` finished loading, and returns how that went.
+    pub fn wait(&mut self, handle: Handle<T>) -> LoadState {
+        while self.state(handle) == LoadState::Loading {
+            match self.receiver.recv() {
+                Ok(finished) => self.finish(finished),
+                Err(_) => break,
+            }
+        }
+        self.state(handle)
+    }
+
+    fn finish(&mut self, (id, asset): Finished<T>) {
+        // Released while it was loading
+        let Some(slot) = self.slots.get_mut(&id) else {
+            return;
+        };
+        slot.entry = match asset {
+            Ok(asset) => Entry::Loaded(asset),
+            Err(e) => {
+                log::warn!("Could not load {}: {:#}", slot.path, e);
+                Entry::Failed(format!("{:#}", e))
+            }
+        };
+    }
+
+    /// The asset once it is loaded.
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        match &self.slots.get(&handle.id)?.entry {
+            Entry::Loaded(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    pub fn state(&self, handle: Handle<T>) -> LoadState {
+        match self.slots.get(&handle.id).map(|slot| &slot.entry) {
+            Some(Entry::Loading) => LoadState::Loading,
+            Some(Entry::Loaded(_)) => LoadState::Loaded,
+            Some(Entry::Failed(e)) => LoadState::Failed(e.clone()),
+            None => LoadState::Unknown,
+        }
+    }
+
+    pub fn path(&self, handle: Handle<T>) -> Option<&str> {
+        self.slots.get(&handle.id).map(|slot| slot.path.as_str())
+    }
+
+    /// References to `handle` that have not been released yet.
+    pub fn references(&self, handle: Handle<T>) -> usize {
+        self.slots.get(&handle.id).map_or(0, |slot| slot.references)
+    }
+
+    /// Adds a reference for one more owner of `handle`.
+    pub fn retain(&mut self, handle: Handle<T>) {
+        if let Some(slot) = self.slots.get_mut(&handle.id) {
+            slot.references += 1;
+        }
+    }
+
+    /// Gives up one reference to `handle`, and frees the asset if it was the last one.
+    /// Returns whether it was freed.
+    pub fn release(&mut self, handle: Handle<T>) -> bool {
+        let Some(slot) = self.slots.get_mut(&handle.id) else {
+            return false;
+        };
+        slot.references -= 1;
+        if slot.references > 0 {
+            return false;
+        }
+        if let Some(slot) = self.slots.remove(&handle.id) {
+            self.ids.remove(&slot.path);
+        }
+        true
+    }
+
+    /// Assets that are loaded, loading or failed to load.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// Textures by file name shared between the materials that use them, so a file is only
+/// uploaded once. A texture is freed with the last material holding it.
+#[derive(Clone, Default)]
+pub struct TextureCache {
+    textures: Arc<Mutex<HashMap<TextureKey, Weak<Texture>>>>,
+}
+
+// File name and whether it is a normal map, which is stored without sRGB conversion
+type TextureKey = (String, bool);
+
+impl TextureCache {
+    /// The texture in `file_name`, loaded unless a material still holds it.
+    pub async fn load(
+        &self,
+        file_name: &str,
+        is_normal_map: bool,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<Arc<Texture>> {
+        let key = (file_name.to_string(), is_normal_map);
+        if let Some(texture) = self.get(&key) {
+            return Ok(texture);
+        }
+        let texture = Arc::new(
+            crate::resources::load_texture(file_name, is_normal_map, device, queue).await?,
+        );
+        let mut textures = self.textures.lock().unwrap();
+        textures.retain(|_, texture| texture.strong_count() > 0);
+        // Another thread may have loaded the same file in the meantime, its copy is kept
+        if let Some(loaded) = textures.get(&key).and_then(Weak::upgrade) {
+            return Ok(loaded);
+        }
+        textures.insert(key, Arc::downgrade(&texture));
+        Ok(texture)
+    }
+
+    fn get(&self, key: &TextureKey) -> Option<Arc<Texture>> {
+        let textures = self.textures.lock().unwrap();
+        textures.get(key).and_then(Weak::upgrade)
+    }
+
+    /// Textures some material still holds.
+    pub fn len(&self) -> usize {
+        let textures = self.textures.lock().unwrap();
+        textures
+            .values()
+            .filter(|texture| texture.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
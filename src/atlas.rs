@@ -0,0 +1,263 @@
+use anyhow::*;
+use image::RgbaImage;
+use std::collections::HashMap;
+
+use crate::block::{BlockFace, BlockKind};
+
+/// Largest atlas edge we are willing to allocate, matches `wgpu::Limits::default()`.
+pub const MAX_ATLAS_SIZE: u32 = 8192;
+/// Upper bound for the mip chain of the atlas. Deeper levels would need huge padding.
+pub const MAX_ATLAS_MIP_LEVELS: u32 = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasRegion {
+    // Pixel rectangle of the tile inside the atlas, without padding
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    // Normalized texture coordinates as [min_u, min_v, max_u, max_v]
+    pub uv: [f32; 4],
+}
+
+pub struct AtlasBuilder {
+    padding: u32,
+    is_normal_map: bool,
+    tiles: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    /// `padding` is the amount of pixels each tile gets extruded by on every side,
+    /// so that filtering near the tile border never samples a neighbouring tile.
+    pub fn new(padding: u32) -> Self {
+        Self {
+            padding,
+            is_normal_map: false,
+            tiles: Vec::new(),
+        }
+    }
+
+    /// Normal maps are stored linearly, so their mips must not be averaged in sRGB space.
+    pub fn is_normal_map(&mut self, is_normal_map: bool) -> &mut Self {
+        self.is_normal_map = is_normal_map;
+        self
+    }
+
+    pub fn add(&mut self, name: &str, image: RgbaImage) -> &mut Self {
+        self.tiles.push((name.to_string(), image));
+        self
+    }
+
+    pub fn build(mut self) -> Result<Atlas> {
+        // Every tile is mipmapped on its own. For a level to line up with the tiles they have
+        // to start on, and be sized in, multiples of the downscale factor of the smallest level.
+        let mip_levels = self
+            .tiles
+            .iter()
+            .map(|(_, tile)| {
+                tile.width()
+                    .trailing_zeros()
+                    .min(tile.height().trailing_zeros())
+            })
+            .min()
+            .unwrap_or(0)
+            .min(MAX_ATLAS_MIP_LEVELS - 1)
+            + 1;
+        let alignment = 1 << (mip_levels - 1);
+        let padding = self.padding.div_ceil(alignment) * alignment;
+
+        // Shelf packing works best with the tallest tiles first
+        self.tiles
+            .sort_by(|(_, a), (_, b)| b.height().cmp(&a.height()).then(b.width().cmp(&a.width())));
+
+        let slots = self
+            .tiles
+            .iter()
+            .map(|(_, image)| (image.width() + 2 * padding, image.height() + 2 * padding))
+            .collect::<Vec<_>>();
+
+        let area: u64 = slots.iter().map(|(w, h)| *w as u64 * *h as u64).sum();
+        let widest = slots.iter().map(|(w, _)| *w).max().unwrap_or(1);
+        let mut width = widest
+            .max((area as f64).sqrt().ceil() as u32)
+            .next_power_of_two();
+        let mut height = width;
+
+        let positions = loop {
+            // A single tile can already be too wide for the largest atlas
+            if width > MAX_ATLAS_SIZE || height > MAX_ATLAS_SIZE {
+                bail!(
+                    "{} tiles do not fit into a {}x{} atlas",
+                    self.tiles.len(),
+                    MAX_ATLAS_SIZE,
+                    MAX_ATLAS_SIZE
+                );
+            }
+            if let Some(positions) = pack_shelves(&slots, width, height) {
+                break positions;
+            }
+            if width > height {
+                height *= 2;
+            } else {
+                width *= 2;
+            }
+        };
+
+        let mut mips = (0..mip_levels)
+            .map(|level| RgbaImage::new(width >> level, height >> level))
+            .collect::<Vec<_>>();
+        let mut regions = HashMap::new();
+        for ((name, tile), (slot_x, slot_y)) in self.tiles.into_iter().zip(positions) {
+            let x = slot_x + padding;
+            let y = slot_y + padding;
+            let tile_mips = crate::texture::mip_chain(tile.clone(), !self.is_normal_map);
+            for (level, (mip, tile_mip)) in mips.iter_mut().zip(&tile_mips).enumerate() {
+                blit_extruded(mip, tile_mip, x >> level, y >> level, padding >> level);
+            }
+
+            let region = AtlasRegion {
+                x,
+                y,
+                width: tile.width(),
+                height: tile.height(),
+                uv: [
+                    x as f32 / width as f32,
+                    y as f32 / height as f32,
+                    (x + tile.width()) as f32 / width as f32,
+                    (y + tile.height()) as f32 / height as f32,
+                ],
+            };
+            if regions.insert(name.clone(), region).is_some() {
+                bail!("Texture {} was added to the atlas twice", name);
+            }
+        }
+
+        Ok(Atlas {
+            mips,
+            regions,
+            padding,
+            is_normal_map: self.is_normal_map,
+        })
+    }
+}
+
+/// Places the slots left to right in rows, opening a new row once the current one is full.
+/// Returns the top left corner of every slot or `None` if they do not fit.
+fn pack_shelves(slots: &[(u32, u32)], width: u32, height: u32) -> Option<Vec<(u32, u32)>> {
+    let mut positions = Vec::with_capacity(slots.len());
+    let (mut cursor_x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+    for &(slot_width, slot_height) in slots {
+        if slot_width > width {
+            return None;
+        }
+        if cursor_x + slot_width > width {
+            shelf_y += shelf_height;
+            cursor_x = 0;
+            shelf_height = 0;
+        }
+        if shelf_y + slot_height > height {
+            return None;
+        }
+        positions.push((cursor_x, shelf_y));
+        cursor_x += slot_width;
+        shelf_height = shelf_height.max(slot_height);
+    }
+    Some(positions)
+}
+
+/// Copies `tile` to (`x`, `y`) and repeats its border pixels `padding` times outwards.
+fn blit_extruded(atlas: &mut RgbaImage, tile: &RgbaImage, x: u32, y: u32, padding: u32) {
+    let (tile_width, tile_height) = tile.dimensions();
+    let padding = padding as i64;
+    for dy in -padding..tile_height as i64 + padding {
+        for dx in -padding..tile_width as i64 + padding {
+            let source_x = dx.clamp(0, tile_width as i64 - 1) as u32;
+            let source_y = dy.clamp(0, tile_height as i64 - 1) as u32;
+            atlas.put_pixel(
+                (x as i64 + dx) as u32,
+                (y as i64 + dy) as u32,
+                *tile.get_pixel(source_x, source_y),
+            );
+        }
+    }
+}
+
+pub struct Atlas {
+    // Level 0 is the full size atlas, each following level was downsampled tile by tile
+    pub mips: Vec<RgbaImage>,
+    regions: HashMap<String, AtlasRegion>,
+    // Pixels every tile was extruded by, rounded up so that it lines up in every mip level
+    padding: u32,
+    is_normal_map: bool,
+}
+
+/// A rectangle of one mip level of the atlas to overwrite, including the padding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasPatch {
+    pub level: u32,
+    // Top left corner in the pixels of that level
+    pub x: u32,
+    pub y: u32,
+    pub image: RgbaImage,
+}
+
+impl Atlas {
+    pub fn image(&self) -> &RgbaImage {
+        &self.mips[0]
+    }
+
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    /// UV rectangle of the texture shown on `face` of a block of the given kind.
+    /// Falls back to the whole atlas if the texture was never packed.
+    pub fn block_uv(&self, kind: BlockKind, face: BlockFace) -> [f32; 4] {
+        self.region(kind.texture(face))
+            .map(|region| region.uv)
+            .unwrap_or([0.0, 0.0, 1.0, 1.0])
+    }
+
+    /// Redraws the tile `name` with `tile`, e.g. the next frame of an animation. Returns
+    /// the tile downsampled and extruded just like when the atlas was built, one patch
+    /// per mip level, ready to be written into the atlas texture.
+    pub fn patch_tile(&self, name: &str, tile: &RgbaImage) -> Result<Vec<AtlasPatch>> {
+        let region = self
+            .region(name)
+            .with_context(|| format!("Texture {} is not in the atlas", name))?;
+        ensure!(
+            tile.dimensions() == (region.width, region.height),
+            "Texture {} is {}x{} in the atlas but the new tile is {}x{}",
+            name,
+            region.width,
+            region.height,
+            tile.width(),
+            tile.height()
+        );
+
+        let tile_mips = crate::texture::mip_chain(tile.clone(), !self.is_normal_map);
+        let patches = (0..self.mips.len() as u32)
+            .zip(tile_mips)
+            .map(|(level, tile_mip)| {
+                let padding = self.padding >> level;
+                let mut image = RgbaImage::new(
+                    tile_mip.width() + 2 * padding,
+                    tile_mip.height() + 2 * padding,
+                );
+                blit_extruded(&mut image, &tile_mip, padding, padding, padding);
+                AtlasPatch {
+                    level,
+                    x: (region.x >> level) - padding,
+                    y: (region.y >> level) - padding,
+                    image,
+                }
+            })
+            .collect();
+        Ok(patches)
+    }
+
+    pub fn save_debug_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        self.image().save(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! Bind group layouts of every pipeline and the `@group` each of them is bound to in the
+//! WGSL files. build.rs includes this file as well and checks every resource the shaders
+//! use against these entries, so it may only depend on `wgpu_types`.
+
+use std::mem::size_of;
+use wgpu_types::{
+    BindGroupLayoutEntry, BindingType, BufferBindingType, BufferSize, SamplerBindingType,
+    ShaderStages, TextureSampleType, TextureViewDimension,
+};
+
+/// Joints a skeleton may have, has to match the size of the `joints` array in the shaders.
+pub const MAX_JOINTS: usize = 64;
+
+/// Bytes of joint matrices every skinned entity gets, a multiple of the uniform buffer
+/// offset alignment so each entity's matrices can be bound with a dynamic offset
+pub const PALETTE_SIZE: u32 = (MAX_JOINTS * size_of::<[[f32; 4]; 4]>()) as u32;
+
+const fn uniform(binding: u32, visibility: ShaderStages) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+const fn texture(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            multisampled: false,
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+const fn sampler(binding: u32, ty: SamplerBindingType) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Sampler(ty),
+        count: None,
+    }
+}
+
+const VERTEX_FRAGMENT: ShaderStages = ShaderStages::VERTEX_FRAGMENT;
+
+/// Diffuse texture and normal map of a material, with their samplers
+pub const TEXTURE_ENTRIES: [BindGroupLayoutEntry; 4] = [
+    texture(0),
+    sampler(1, SamplerBindingType::Filtering),
+    texture(2),
+    sampler(3, SamplerBindingType::Filtering),
+];
+
+pub const CAMERA_ENTRIES: [BindGroupLayoutEntry; 1] = [uniform(0, VERTEX_FRAGMENT)];
+
+pub const LIGHT_ENTRIES: [BindGroupLayoutEntry; 5] = [
+    uniform(0, VERTEX_FRAGMENT),
+    // Sun and shadow cascades
+    uniform(1, ShaderStages::FRAGMENT),
+    BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            multisampled: false,
+            sample_type: TextureSampleType::Depth,
+            view_dimension: TextureViewDimension::D2Array,
+        },
+        count: None,
+    },
+    sampler(3, SamplerBindingType::Comparison),
+    // Sky colours for the distance fog
+    uniform(4, ShaderStages::FRAGMENT),
+];
+
+/// Joint matrices of one skinned entity, picked out of a larger buffer by the offset.
+pub const SKIN_ENTRIES: [BindGroupLayoutEntry; 1] = [BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStages::VERTEX,
+    ty: BindingType::Buffer {
+        ty: BufferBindingType::Uniform,
+        has_dynamic_offset: true,
+        min_binding_size: BufferSize::new(PALETTE_SIZE as u64),
+    },
+    count: None,
+}];
+
+/// The light space matrix of one shadow cascade
+pub const SHADOW_CASCADE_ENTRIES: [BindGroupLayoutEntry; 1] = [uniform(0, ShaderStages::VERTEX)];
+
+pub const SKY_ENTRIES: [BindGroupLayoutEntry; 1] = [uniform(0, ShaderStages::FRAGMENT)];
+
+pub const OUTLINE_ENTRIES: [BindGroupLayoutEntry; 1] = [uniform(0, VERTEX_FRAGMENT)];
+
+pub const OVERLAY_PROJECTION_ENTRIES: [BindGroupLayoutEntry; 1] =
+    [uniform(0, ShaderStages::VERTEX)];
+
+/// The sprite sheet or glyph atlas of an overlay batch
+pub const OVERLAY_TEXTURE_ENTRIES: [BindGroupLayoutEntry; 2] =
+    [texture(0), sampler(1, SamplerBindingType::Filtering)];
+
+type BindGroups = &'static [&'static [BindGroupLayoutEntry]];
+
+/// The layout of every `@group` of each WGSL file, in the order of the pipeline layouts.
+/// Pipelines of the same file may leave out the groups their entry points do not use.
+pub const SHADER_BIND_GROUPS: &[(&str, BindGroups)] = &[
+    (
+        "shader.wgsl",
+        &[
+            &TEXTURE_ENTRIES,
+            &CAMERA_ENTRIES,
+            &LIGHT_ENTRIES,
+            &SKIN_ENTRIES,
+        ],
+    ),
+    ("light.wgsl", &[&CAMERA_ENTRIES, &LIGHT_ENTRIES]),
+    ("shadow.wgsl", &[&SHADOW_CASCADE_ENTRIES, &SKIN_ENTRIES]),
+    ("sky.wgsl", &[&SKY_ENTRIES]),
+    ("outline.wgsl", &[&CAMERA_ENTRIES, &OUTLINE_ENTRIES]),
+    (
+        "overlay.wgsl",
+        &[&OVERLAY_PROJECTION_ENTRIES, &OVERLAY_TEXTURE_ENTRIES],
+    ),
+    (
+        "particle.wgsl",
+        &[&TEXTURE_ENTRIES, &CAMERA_ENTRIES, &LIGHT_ENTRIES],
+    ),
+];
+
+/// Checks that every resource an entry point of `module` uses has an entry in `groups` at
+/// its `@group` and `@binding`, of the same kind and visible to the entry point's stage.
+pub fn check_bind_groups(
+    module: &naga::Module,
+    info: &naga::valid::ModuleInfo,
+    groups: &[&[BindGroupLayoutEntry]],
+) -> Result<(), String> {
+    for (index, entry_point) in module.entry_points.iter().enumerate() {
+        let stage = match entry_point.stage {
+            naga::ShaderStage::Vertex => ShaderStages::VERTEX,
+            naga::ShaderStage::Fragment => ShaderStages::FRAGMENT,
+            naga::ShaderStage::Compute => ShaderStages::COMPUTE,
+        };
+        let function = info.get_entry_point(index);
+        for (handle, global) in module.global_variables.iter() {
+            let Some(binding) = &global.binding else {
+                continue;
+            };
+            if function[handle].is_empty() {
+                continue;
+            }
+            let name = global.name.clone().unwrap_or_default();
+            let place = format!("@group({}) @binding({})", binding.group, binding.binding);
+            let entry = groups
+                .get(binding.group as usize)
+                .and_then(|entries| entries.iter().find(|e| e.binding == binding.binding))
+                .ok_or_else(|| {
+                    format!(
+                        "`{}` in `{}` reads {} but no bind group layout has it",
+                        name, entry_point.name, place
+                    )
+                })?;
+            if !entry.visibility.contains(stage) {
+                return Err(format!(
+                    "`{}` at {} is used by `{}` but only visible to {:?}",
+                    name, place, entry_point.name, entry.visibility
+                ));
+            }
+            let kind = binding_kind(module, global);
+            if kind != layout_kind(&entry.ty) {
+                return Err(format!(
+                    "`{}` at {} is {:?} in the shader but {:?} in Rust",
+                    name, place, kind, entry.ty
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a binding holds, as far as the shader and the layout have to agree on it.
+#[derive(Debug, PartialEq)]
+enum BindingKind {
+    Uniform,
+    Storage {
+        read_only: bool,
+    },
+    Texture {
+        depth: bool,
+        arrayed: bool,
+        multisampled: bool,
+    },
+    Sampler {
+        comparison: bool,
+    },
+}
+
+fn binding_kind(module: &naga::Module, global: &naga::GlobalVariable) -> Option<BindingKind> {
+    match (global.space, &module.types[global.ty].inner) {
+        (naga::AddressSpace::Uniform, _) => Some(BindingKind::Uniform),
+        (naga::AddressSpace::Storage { access }, _) => Some(BindingKind::Storage {
+            read_only: !access.contains(naga::StorageAccess::STORE),
+        }),
+        (
+            naga::AddressSpace::Handle,
+            naga::TypeInner::Image {
+                dim: naga::ImageDimension::D2,
+                arrayed,
+                class,
+            },
+        ) => match *class {
+            naga::ImageClass::Sampled { multi, .. } => Some(BindingKind::Texture {
+                depth: false,
+                arrayed: *arrayed,
+                multisampled: multi,
+            }),
+            naga::ImageClass::Depth { multi } => Some(BindingKind::Texture {
+                depth: true,
+                arrayed: *arrayed,
+                multisampled: multi,
+            }),
+            naga::ImageClass::Storage { .. } => None,
+        },
+        (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => {
+            Some(BindingKind::Sampler {
+                comparison: *comparison,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn layout_kind(ty: &BindingType) -> Option<BindingKind> {
+    match *ty {
+        BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            ..
+        } => Some(BindingKind::Uniform),
+        BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            ..
+        } => Some(BindingKind::Storage { read_only }),
+        BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        } => Some(BindingKind::Texture {
+            depth: sample_type == TextureSampleType::Depth,
+            arrayed: match view_dimension {
+                TextureViewDimension::D2 => false,
+                TextureViewDimension::D2Array => true,
+                _ => return None,
+            },
+            multisampled,
+        }),
+        BindingType::Sampler(ty) => Some(BindingKind::Sampler {
+            comparison: ty == SamplerBindingType::Comparison,
+        }),
+        _ => None,
+    }
+}
@@ -1,19 +1,133 @@
 use cgmath::{Quaternion, Vector3, Zero};
 
-use crate::Instance;
+use crate::{animation::TextureAnimation, atlas::Atlas, Instance};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlockKind {
+    Dirt,
+    Cobble,
+    Glass,
+    Water,
+    Leaves,
+}
+
+/// Which render pass a block is drawn in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlockLayer {
+    // Fully opaque, drawn first in any order
+    Opaque,
+    // Either fully opaque or fully transparent per pixel, e.g. leaves
+    Cutout,
+    // Blended with whatever is behind, drawn last from back to front
+    Translucent,
+}
+
+impl BlockKind {
+    pub const ALL: [BlockKind; 5] = [
+        BlockKind::Dirt,
+        BlockKind::Cobble,
+        BlockKind::Glass,
+        BlockKind::Water,
+        BlockKind::Leaves,
+    ];
+
+    /// File name in `res/` of the texture drawn on the given face.
+    pub fn texture(&self, face: BlockFace) -> &'static str {
+        match (self, face) {
+            (BlockKind::Dirt, _) => "dirt.png",
+            (BlockKind::Cobble, _) => "cobble-diffuse.png",
+            (BlockKind::Glass, _) => "glass.png",
+            (BlockKind::Water, _) => "water.png",
+            (BlockKind::Leaves, _) => "leaves.png",
+        }
+    }
+
+    /// File name in `res/` of the normal map for the given face, if the texture has one.
+    pub fn normal_texture(&self, face: BlockFace) -> Option<&'static str> {
+        match (self, face) {
+            (BlockKind::Cobble, _) => Some("cobble-normal.png"),
+            _ => None,
+        }
+    }
+
+    /// How the texture on the given face is animated, `None` for a single still image.
+    /// Animated textures are strips with every frame below the previous one.
+    pub fn animation(&self, face: BlockFace) -> Option<TextureAnimation> {
+        match (self, face) {
+            (BlockKind::Water, _) => {
+                Some(TextureAnimation::new(8, 0.25, true).expect("The water animation is valid"))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn layer(&self) -> BlockLayer {
+        match self {
+            BlockKind::Dirt | BlockKind::Cobble => BlockLayer::Opaque,
+            BlockKind::Leaves => BlockLayer::Cutout,
+            BlockKind::Glass | BlockKind::Water => BlockLayer::Translucent,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlockFace {
+    Top,
+    Bottom,
+    North,
+    South,
+    East,
+    West,
+}
+
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::Top,
+        BlockFace::Bottom,
+        BlockFace::North,
+        BlockFace::South,
+        BlockFace::East,
+        BlockFace::West,
+    ];
+
+    /// Unit vector pointing out of the face, North is -z and East is +x.
+    pub fn normal(&self) -> Vector3<i32> {
+        match self {
+            BlockFace::Top => Vector3::unit_y(),
+            BlockFace::Bottom => -Vector3::unit_y(),
+            BlockFace::North => -Vector3::unit_z(),
+            BlockFace::South => Vector3::unit_z(),
+            BlockFace::East => Vector3::unit_x(),
+            BlockFace::West => -Vector3::unit_x(),
+        }
+    }
+
+    pub fn opposite(&self) -> BlockFace {
+        match self {
+            BlockFace::Top => BlockFace::Bottom,
+            BlockFace::Bottom => BlockFace::Top,
+            BlockFace::North => BlockFace::South,
+            BlockFace::South => BlockFace::North,
+            BlockFace::East => BlockFace::West,
+            BlockFace::West => BlockFace::East,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Block {
     pub position: Vector3<f32>,
+    pub kind: BlockKind,
 }
 
 impl Block {
-    fn render(&self) {}
-
-    pub fn to_instance(&self) -> Instance {
+    pub fn to_instance(&self, atlas: &Atlas) -> Instance {
         Instance {
             position: self.position,
             rotation: Quaternion::zero(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            // The cube mesh shares one set of texture coordinates between all faces
+            uv_rect: atlas.block_uv(self.kind, BlockFace::North),
         }
     }
 }
@@ -0,0 +1,174 @@
+use cgmath::{Deg, Point3, Rad, Vector2, Vector3};
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{block::BlockFace, lod::LOD_LEVELS, renderer::RenderStats, world::World};
+
+/// Number of frames kept for the frame time graph and the average frame rate
+pub const FRAME_HISTORY: usize = 120;
+
+/// Durations of the most recent frames, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct FrameTimes {
+    times: VecDeque<Duration>,
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, dt: Duration) {
+        if self.times.len() == FRAME_HISTORY {
+            self.times.pop_front();
+        }
+        self.times.push_back(dt);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.times.iter().copied()
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.times.iter().sum::<Duration>() / self.times.len() as u32
+    }
+
+    pub fn max(&self) -> Duration {
+        self.times.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Frames per second averaged over the whole history.
+    pub fn fps(&self) -> f32 {
+        let average = self.average().as_secs_f32();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Everything shown on the debug screen. Collected from plain values, so it does not
+/// need a window or a rendered frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugStats {
+    pub fps: f32,
+    pub frame_time: Duration,
+    pub max_frame_time: Duration,
+    pub position: Point3<f32>,
+    // Block and chunk the player is in
+    pub block: Vector3<i32>,
+    pub chunk: Vector2<i32>,
+    pub yaw: Deg<f32>,
+    pub pitch: Deg<f32>,
+    // Horizontal direction the player is looking in
+    pub facing: BlockFace,
+    pub target: Option<(Vector3<i32>, BlockFace)>,
+    pub blocks: usize,
+    pub loaded_chunks: usize,
+    pub render: RenderStats,
+}
+
+impl DebugStats {
+    pub fn collect(
+        world: &World,
+        frame_times: &FrameTimes,
+        position: Point3<f32>,
+        yaw: Rad<f32>,
+        pitch: Rad<f32>,
+        target: Option<(Vector3<i32>, BlockFace)>,
+        render: RenderStats,
+    ) -> Self {
+        let block = crate::world::block_at(position);
+        Self {
+            fps: frame_times.fps(),
+            frame_time: frame_times.average(),
+            max_frame_time: frame_times.max(),
+            position,
+            block,
+            chunk: crate::world::chunk_of(block),
+            yaw: yaw.into(),
+            pitch: pitch.into(),
+            facing: facing(yaw),
+            target,
+            blocks: world.blocks().len(),
+            loaded_chunks: world.loaded_chunks(),
+            render,
+        }
+    }
+
+    /// The text of the debug screen, one entry per line.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{:.0} fps ({:.2} ms avg, {:.2} ms max)",
+                self.fps,
+                self.frame_time.as_secs_f64() * 1000.0,
+                self.max_frame_time.as_secs_f64() * 1000.0
+            ),
+            format!(
+                "XYZ: {:.3} / {:.3} / {:.3}",
+                self.position.x, self.position.y, self.position.z
+            ),
+            format!("Block: {} {} {}", self.block.x, self.block.y, self.block.z),
+            format!("Chunk: {} {}", self.chunk.x, self.chunk.y),
+            format!(
+                "Facing: {:?} (yaw {:.1}, pitch {:.1})",
+                self.facing,
+                self.yaw.0.rem_euclid(360.0),
+                self.pitch.0
+            ),
+        ];
+        lines.push(match self.target {
+            Some((coords, face)) => format!(
+                "Targeted block: {} {} {} ({:?} face)",
+                coords.x, coords.y, coords.z, face
+            ),
+            None => "Targeted block: none".to_string(),
+        });
+        lines.extend([
+            format!("Blocks: {} in {} chunks", self.blocks, self.loaded_chunks),
+            format!(
+                "Draw calls: {}, triangles: {}",
+                self.render.draw_calls, self.render.triangles
+            ),
+            format!(
+                "Chunks drawn: {} (full detail to {}x merged)",
+                self.render
+                    .chunks
+                    .iter()
+                    .map(|count| count.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" / "),
+                1 << LOD_LEVELS
+            ),
+            format!("Occluded: {}", self.render.occluded),
+            format!("Entities: {}", self.render.entities),
+            format!("Particles: {}", self.render.particles),
+            format!(
+                "GPU buffers: {:.1} KiB",
+                self.render.buffer_memory as f64 / 1024.0
+            ),
+        ]);
+        lines
+    }
+}
+
+/// Compass direction of `yaw`, using the same axes as the block faces:
+/// east is towards +x and south towards +z.
+pub fn facing(yaw: Rad<f32>) -> BlockFace {
+    let (sin, cos) = yaw.0.sin_cos();
+    if cos.abs() >= sin.abs() {
+        if cos > 0.0 {
+            BlockFace::East
+        } else {
+            BlockFace::West
+        }
+    } else if sin > 0.0 {
+        BlockFace::South
+    } else {
+        BlockFace::North
+    }
+}
@@ -0,0 +1,250 @@
+//! Mobs, dropped items and props: anything drawn with a model of its own instead of the
+//! block cube. Models are loaded once into a registry and shared, and every entity places
+//! one instance of its model in the world. Entities with the same model are drawn together
+//! in one instanced draw per mesh. Skinned models are the exception, every entity with
+//! one is drawn on its own in the pose of the animation it plays.
+
+use cgmath::{Matrix4, One, Quaternion, Vector3};
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{
+    assets::{Assets, Handle, LoadState, TextureCache},
+    model::Model,
+    skeleton::AnimationPlayer,
+    Instance,
+};
+
+/// Identifies a model loaded into a [`ModelRegistry`].
+pub type ModelId = Handle<Model>;
+
+/// OBJ and glTF models by file name, every file is only loaded once. Models count the
+/// loads of their file and are freed once each of them is released, together with the
+/// textures no other model uses.
+#[derive(Default)]
+pub struct ModelRegistry {
+    models: Assets<Model>,
+    textures: TextureCache,
+}
+
+impl ModelRegistry {
+    /// Loads `file_name` with its materials, or adds a reference to the id it already has.
+    /// Waits for the model if it is still loading in the background.
+    pub async fn load(
+        &mut self,
+        file_name: &str,
+        renderer: &crate::renderer::Renderer,
+    ) -> anyhow::Result<ModelId> {
+        if let Some(id) = self.models.handle(file_name) {
+            self.models.retain(id);
+            return match self.models.wait(id) {
+                LoadState::Loaded => Ok(id),
+                state => {
+                    self.models.release(id);
+                    Err(anyhow::anyhow!("Could not load {}: {:?}", file_name, state))
+                }
+            };
+        }
+        let model = crate::resources::load_model(
+            file_name,
+            &renderer.device,
+            &renderer.queue,
+            &renderer.texture_bind_group_layout,
+            &self.textures,
+        )
+        .await?;
+        Ok(self.models.insert(file_name, model))
+    }
+
+    /// Starts loading `file_name` on the loader thread, or adds a reference to the id it
+    /// already has. Entities with the model are not drawn until [`ModelRegistry::update`]
+    /// sees it finish.
+    pub fn load_in_background(
+        &mut self,
+        file_name: &str,
+        renderer: &crate::renderer::Renderer,
+    ) -> ModelId {
+        let name = file_name.to_string();
+        let device = renderer.device.clone();
+        let queue = renderer.queue.clone();
+        let layout = renderer.texture_bind_group_layout.clone();
+        let textures = self.textures.clone();
+        self.models.load_async_with(file_name, move || async move {
+            Ok(crate::resources::load_model(&name, &device, &queue, &layout, &textures).await?)
+        })
+    }
+
+    /// Takes in the models that finished loading in the background.
+    pub fn update(&mut self) {
+        self.models.update();
+    }
+
+    /// Gives up a reference from [`ModelRegistry::load`], the model is freed with the last
+    /// one. Returns whether it was.
+    pub fn release(&mut self, id: ModelId) -> bool {
+        self.models.release(id)
+    }
+
+    /// References to `id` that have not been released yet.
+    pub fn references(&self, id: ModelId) -> usize {
+        self.models.references(id)
+    }
+
+    pub fn state(&self, id: ModelId) -> LoadState {
+        self.models.state(id)
+    }
+
+    /// The model once it is loaded.
+    pub fn get(&self, id: ModelId) -> Option<&Model> {
+        self.models.get(id)
+    }
+
+    /// Models that are loaded or still loading.
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// Textures the loaded models share.
+    pub fn textures(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn skeleton(&self, id: ModelId) -> Option<&crate::skeleton::Skeleton> {
+        self.get(id)?.skeleton.as_ref()
+    }
+
+    /// Clips of `id`, none until it is loaded.
+    pub fn animations(&self, id: ModelId) -> &[crate::skeleton::AnimationClip] {
+        self.get(id).map_or(&[], |model| &model.animations)
+    }
+
+    /// Index of the animation of `id` called `name`, for [`AnimationPlayer::new`].
+    pub fn animation(&self, id: ModelId, name: &str) -> Option<usize> {
+        self.get(id)?.animation(name)
+    }
+
+    /// The joint matrices `entity` is drawn with, `None` if its model has no skeleton.
+    /// Entities without an animation are shown in the rest pose.
+    pub fn joint_matrices(&self, entity: &Entity) -> Option<Vec<Matrix4<f32>>> {
+        let model = self.get(entity.model)?;
+        let skeleton = model.skeleton.as_ref()?;
+        let mut pose = skeleton.rest_pose();
+        if let Some(player) = entity.animation {
+            if let Some(clip) = model.animations.get(player.clip) {
+                clip.sample(player.time, &mut pose);
+            }
+        }
+        Some(skeleton.joint_matrices(&pose))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Entity {
+    pub model: ModelId,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    // Size along each axis relative to the model
+    pub scale: Vector3<f32>,
+    // Only used by models with a skeleton
+    pub animation: Option<AnimationPlayer>,
+}
+
+impl Entity {
+    /// An entity showing `model` at its original size and orientation.
+    pub fn new(model: ModelId, position: Vector3<f32>) -> Self {
+        Self {
+            model,
+            position,
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            animation: None,
+        }
+    }
+
+    /// Takes the model from its own space into the world: scaled, then rotated, then moved.
+    pub fn transform(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn to_instance(&self) -> Instance {
+        Instance {
+            position: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+            // Models bring their own textures, which are used whole
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+        }
+    }
+}
+
+/// Identifies an entity in [`Entities`], it stays valid until the entity is removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId(u64);
+
+#[derive(Default)]
+pub struct Entities {
+    entities: BTreeMap<EntityId, Entity>,
+    next_id: u64,
+}
+
+impl Entities {
+    pub fn spawn(&mut self, entity: Entity) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        self.entities.insert(id, entity);
+        id
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<Entity> {
+        self.entities.remove(&id)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    /// For moving, turning and resizing an entity.
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.entities.iter().map(|(id, entity)| (*id, entity))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Plays the animations of all entities on, `models` has the length of every clip.
+    pub fn update(&mut self, dt: Duration, models: &ModelRegistry) {
+        for entity in self.entities.values_mut() {
+            if let Some(player) = &mut entity.animation {
+                let duration = models
+                    .animations(entity.model)
+                    .get(player.clip)
+                    .map_or(0.0, |clip| clip.duration);
+                player.advance(dt.as_secs_f32(), duration);
+            }
+        }
+    }
+
+    /// The entities `is_visible` keeps, grouped by model and ordered by model id and then
+    /// by the order the entities were spawned in.
+    pub fn batches(&self, is_visible: impl Fn(&Entity) -> bool) -> Vec<(ModelId, Vec<&Entity>)> {
+        let mut batches: BTreeMap<ModelId, Vec<&Entity>> = BTreeMap::new();
+        for entity in self.entities.values().filter(|entity| is_visible(entity)) {
+            batches.entry(entity.model).or_default().push(entity);
+        }
+        batches.into_iter().collect()
+    }
+}
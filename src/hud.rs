@@ -0,0 +1,173 @@
+use crate::{
+    atlas::Atlas,
+    block::{BlockFace, BlockKind},
+    debug::{DebugStats, FrameTimes, FRAME_HISTORY},
+    overlay::{Overlay, Rect, SpriteTexture},
+    renderer::Renderer,
+    text::Font,
+};
+
+const CROSSHAIR_SIZE: f32 = 16.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+const SLOT_SIZE: f32 = 40.0;
+const SLOT_GAP: f32 = 4.0;
+const HOTBAR_MARGIN: f32 = 8.0;
+const FONT_SIZE: f32 = 16.0;
+const TEXT_MARGIN: f32 = 4.0;
+// Pixels per millisecond in the frame time graph
+const GRAPH_SCALE: f32 = 3.0;
+const GRAPH_BAR_WIDTH: f32 = 2.0;
+
+/// The in-game interface: a crosshair in the middle of the screen, a hotbar
+/// with the placeable blocks along the bottom and the frame rate in the corner.
+pub struct Hud {
+    atlas: SpriteTexture,
+    font: Font,
+}
+
+impl Hud {
+    /// `font_data` is the TTF file used for all text.
+    pub fn new(renderer: &mut Renderer, atlas: &Atlas, font_data: &[u8]) -> anyhow::Result<Self> {
+        let atlas = renderer.overlay.add_texture(
+            &renderer.device,
+            &renderer.queue,
+            &atlas.mips,
+            "hud_atlas",
+        )?;
+        let font = Font::new(renderer, font_data, FONT_SIZE)?;
+        Ok(Self { atlas, font })
+    }
+
+    pub fn draw(&self, overlay: &mut Overlay, atlas: &Atlas, selected_block: BlockKind) {
+        let width = overlay.width() as f32;
+        let height = overlay.height() as f32;
+
+        let crosshair_color = [1.0, 1.0, 1.0, 0.8];
+        overlay.rect(
+            Rect::centered(
+                width / 2.0,
+                height / 2.0,
+                CROSSHAIR_SIZE,
+                CROSSHAIR_THICKNESS,
+            ),
+            crosshair_color,
+        );
+        overlay.rect(
+            Rect::centered(
+                width / 2.0,
+                height / 2.0,
+                CROSSHAIR_THICKNESS,
+                CROSSHAIR_SIZE,
+            ),
+            crosshair_color,
+        );
+
+        let slots = BlockKind::ALL.len() as f32;
+        let hotbar_width = slots * SLOT_SIZE + (slots - 1.0) * SLOT_GAP;
+        let left = (width - hotbar_width) / 2.0;
+        let top = height - HOTBAR_MARGIN - SLOT_SIZE;
+        for (index, kind) in BlockKind::ALL.iter().enumerate() {
+            let slot = Rect::new(
+                left + index as f32 * (SLOT_SIZE + SLOT_GAP),
+                top,
+                SLOT_SIZE,
+                SLOT_SIZE,
+            );
+            if *kind == selected_block {
+                overlay.rect(slot.inset(-2.0), [1.0, 1.0, 1.0, 0.9]);
+            }
+            overlay.rect(slot, [0.0, 0.0, 0.0, 0.5]);
+            overlay.sprite(
+                self.atlas,
+                slot.inset(6.0),
+                atlas.block_uv(*kind, BlockFace::North),
+                [1.0; 4],
+            );
+        }
+    }
+
+    /// Frame rate in the top left corner.
+    pub fn draw_fps(&self, overlay: &mut Overlay, fps: f32) {
+        self.draw_text_box(overlay, &format!("FPS {:.0}", fps));
+    }
+
+    /// The debug screen: statistics in the top left corner and a graph of the recent
+    /// frame times in the bottom left corner.
+    pub fn draw_debug(&self, overlay: &mut Overlay, stats: &DebugStats, frame_times: &FrameTimes) {
+        self.draw_text_box(overlay, &stats.lines().join("\n"));
+
+        let graph_height = 33.3 * GRAPH_SCALE;
+        // Just above the hotbar so the two never overlap
+        let bottom = overlay.height() as f32 - HOTBAR_MARGIN - SLOT_SIZE - 2.0 * TEXT_MARGIN;
+        overlay.rect(
+            Rect::new(
+                TEXT_MARGIN,
+                bottom - graph_height,
+                FRAME_HISTORY as f32 * GRAPH_BAR_WIDTH,
+                graph_height,
+            ),
+            [0.0, 0.0, 0.0, 0.4],
+        );
+        for (index, frame_time) in frame_times.iter().enumerate() {
+            let milliseconds = frame_time.as_secs_f32() * 1000.0;
+            let color = if milliseconds <= 16.7 {
+                [0.2, 0.9, 0.2, 0.9]
+            } else if milliseconds <= 33.3 {
+                [0.9, 0.9, 0.2, 0.9]
+            } else {
+                [0.9, 0.2, 0.2, 0.9]
+            };
+            let bar_height = (milliseconds * GRAPH_SCALE).min(graph_height);
+            overlay.rect(
+                Rect::new(
+                    TEXT_MARGIN + index as f32 * GRAPH_BAR_WIDTH,
+                    bottom - bar_height,
+                    GRAPH_BAR_WIDTH,
+                    bar_height,
+                ),
+                color,
+            );
+        }
+        // Marks 60 fps, the top of the graph is 30 fps
+        overlay.rect(
+            Rect::new(
+                TEXT_MARGIN,
+                bottom - 16.7 * GRAPH_SCALE,
+                FRAME_HISTORY as f32 * GRAPH_BAR_WIDTH,
+                1.0,
+            ),
+            [1.0, 1.0, 1.0, 0.6],
+        );
+    }
+
+    /// Shader compilation errors in red in the top right corner, one after the other.
+    pub fn draw_errors(&self, overlay: &mut Overlay, errors: &[&str]) {
+        let mut y = TEXT_MARGIN;
+        for error in errors {
+            let (text_width, text_height) = self.font.measure(error);
+            let x = (overlay.width() as f32 - TEXT_MARGIN - text_width).max(TEXT_MARGIN);
+            self.draw_text_at(overlay, x, y, error, [1.0, 0.3, 0.3, 1.0]);
+            y += text_height + 3.0 * TEXT_MARGIN;
+        }
+    }
+
+    /// White text on a dark background in the top left corner.
+    fn draw_text_box(&self, overlay: &mut Overlay, text: &str) {
+        self.draw_text_at(
+            overlay,
+            TEXT_MARGIN,
+            TEXT_MARGIN,
+            text,
+            [1.0, 1.0, 1.0, 1.0],
+        );
+    }
+
+    fn draw_text_at(&self, overlay: &mut Overlay, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        let (text_width, text_height) = self.font.measure(text);
+        overlay.rect(
+            Rect::new(x, y, text_width, text_height).inset(-TEXT_MARGIN),
+            [0.0, 0.0, 0.0, 0.4],
+        );
+        self.font.draw(overlay, x, y, text, color);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{ops::Add, time::Duration};
+use std::{collections::BTreeMap, ops::Add, time::Duration};
 
 use camera::Camera;
 use cgmath::{Angle, Deg, InnerSpace, Vector2, Vector3};
@@ -9,29 +9,70 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
-mod block;
+pub mod animation;
+pub mod assets;
+pub mod atlas;
+mod bind_group_layouts;
+pub mod block;
 mod camera;
+pub mod debug;
+pub mod entity;
+mod hud;
+pub mod lod;
 mod model;
+pub mod offscreen;
+mod outline;
+mod overlay;
+pub mod particles;
 mod player;
-mod resources;
+pub mod renderer;
+pub mod resources;
+pub mod screenshot;
+mod shader_checks;
+pub mod shaders;
+pub mod shadow;
+pub mod skeleton;
+pub mod sky;
+mod text;
 mod texture;
-mod world;
+pub mod validate;
+mod vertex_layouts;
+pub mod visibility;
+pub mod world;
 
 #[derive(Clone)]
-struct Instance {
+pub struct Instance {
     position: cgmath::Vector3<f32>,
     rotation: cgmath::Quaternion<f32>,
+    // Size along each axis relative to the mesh, boxes for distant terrain are stretched
+    scale: cgmath::Vector3<f32>,
+    // Region of the bound texture used by this instance as [min_u, min_v, max_u, max_v]
+    uv_rect: [f32; 4],
 }
 
 impl Instance {
     fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
+                * cgmath::Matrix4::from(self.rotation)
+                * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z))
             .into(),
-            normal: cgmath::Matrix3::from(self.rotation).into(),
+            normal: self.normal_matrix().into(),
+            uv_rect: self.uv_rect,
         }
     }
+
+    /// The inverse transpose of rotation * scale, which for a rotation is the rotation
+    /// with the inverse scale. Without it normals of stretched models lean towards their
+    /// longer axis.
+    fn normal_matrix(&self) -> cgmath::Matrix3<f32> {
+        let rotation = cgmath::Matrix3::from(self.rotation);
+        cgmath::Matrix3::from_cols(
+            rotation.x / self.scale.x,
+            rotation.y / self.scale.y,
+            rotation.z / self.scale.z,
+        )
+    }
 }
 
 #[repr(C)]
@@ -40,6 +81,7 @@ impl Instance {
 struct InstanceRaw {
     model: [[f32; 4]; 4],
     normal: [[f32; 3]; 3],
+    uv_rect: [f32; 4],
 }
 
 impl model::Vertex for InstanceRaw {
@@ -51,47 +93,41 @@ impl model::Vertex for InstanceRaw {
             // This means that our shaders will only change to use the next
             // instance when the shader starts processing a new instance
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We don't have to do this in code though.
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                    shader_location: 9,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
-                    shader_location: 10,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
-                    shader_location: 11,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-            ],
+            attributes: &vertex_layouts::INSTANCE_ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(dead_code)]
+struct ParticleRaw {
+    position: [f32; 3],
+    size: f32,
+    uv_rect: [f32; 4],
+    color: [f32; 4],
+    glow: f32,
+}
+
+impl From<&particles::Particle> for ParticleRaw {
+    fn from(particle: &particles::Particle) -> Self {
+        ParticleRaw {
+            position: particle.position.into(),
+            size: particle.size,
+            uv_rect: particle.uv_rect,
+            color: particle.current_color(),
+            glow: particle.glow,
+        }
+    }
+}
+
+impl model::Vertex for ParticleRaw {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ParticleRaw>() as wgpu::BufferAddress,
+            // Every particle is one quad, its corners come from the vertex index
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &vertex_layouts::PARTICLE_ATTRIBUTES,
         }
     }
 }
@@ -110,8 +146,28 @@ struct State {
     world: crate::world::World,
     player: player::Player,
     camera_controller: player::CameraController,
+    hud: hud::Hud,
+    frame_times: debug::FrameTimes,
+    // Statistics of the last frame that was rendered
+    render_stats: renderer::RenderStats,
+    // Toggled with F3
+    show_debug: bool,
+    // Kind of block placed with the right mouse button
+    selected_block: block::BlockKind,
+    // Scale of the screenshot to take with the next frame, 1 captures the window as is
+    screenshot: Option<u32>,
+    // Only set when shader hot reloading is enabled
+    shader_watcher: Option<shaders::ShaderWatcher>,
+    // Shaders that failed to reload, they keep their previous pipeline until fixed
+    shader_errors: BTreeMap<shaders::Shader, String>,
 }
 
+// Multiple of the window size rendered for high resolution screenshots
+const HIGH_RES_SCREENSHOT_SCALE: u32 = 4;
+// Page up and page down double and halve the render distance within these limits
+const MIN_RENDER_DISTANCE: f32 = 64.0;
+const MAX_RENDER_DISTANCE: f32 = 8192.0;
+
 impl State {
     async fn new(window: Window) -> Self {
         let camera = Camera::new(window).await;
@@ -122,15 +178,39 @@ impl State {
             camera,
         );
 
-        let world = crate::world::World::new(player.camera_mut()).await;
+        let world = crate::world::World::new(&player.camera().renderer).await;
+        player
+            .camera_mut()
+            .renderer
+            .set_light((100.0, 30.0, 100.0).into(), (1.0, 1.0, 1.0).into());
 
         let camera_controller = player::CameraController::new(14.0, 1.2);
+        let font_data = resources::load_binary("fonts/DejaVuSansMono.ttf")
+            .await
+            .unwrap();
+        let hud =
+            hud::Hud::new(&mut player.camera_mut().renderer, world.atlas(), &font_data).unwrap();
 
-        Self {
+        let mut state = Self {
             world,
             player,
             camera_controller,
+            hud,
+            frame_times: debug::FrameTimes::new(),
+            render_stats: renderer::RenderStats::default(),
+            show_debug: false,
+            selected_block: block::BlockKind::Dirt,
+            screenshot: None,
+            shader_watcher: shaders::ShaderWatcher::from_env(),
+            shader_errors: BTreeMap::new(),
+        };
+        // Resource packs may bring their own shaders
+        for (shader, source) in shaders::overrides(&resources::asset_sources()) {
+            if state.apply_shader(shader, source) {
+                log::info!("Using {} from the assets", shader.file_name());
+            }
         }
+        state
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -144,6 +224,68 @@ impl State {
 
     fn input(&mut self, event: &WindowEvent) -> bool {
         match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if Self::block_for_key(*key).is_some() => {
+                self.selected_block = Self::block_for_key(*key).unwrap();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F3),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.show_debug = !self.show_debug;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode:
+                            Some(key @ (VirtualKeyCode::PageUp | VirtualKeyCode::PageDown)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let renderer = &mut self.player.camera_mut().renderer;
+                let mut settings = renderer.lod_settings();
+                settings.render_distance = if *key == VirtualKeyCode::PageUp {
+                    settings.render_distance * 2.0
+                } else {
+                    settings.render_distance / 2.0
+                }
+                .clamp(MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE);
+                renderer.set_lod_settings(settings);
+                log::info!("Render distance {}", settings.render_distance);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key @ (VirtualKeyCode::F2 | VirtualKeyCode::F4)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.screenshot = Some(if *key == VirtualKeyCode::F2 {
+                    1
+                } else {
+                    HIGH_RES_SCREENSHOT_SCALE
+                });
+                true
+            }
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -157,14 +299,20 @@ impl State {
                 if *state == ElementState::Pressed {
                     if *button == MouseButton::Left {
                         if let Some(coords) = self.player.looking_at(&self.world) {
-                            self.world.destroy(&coords);
+                            if let Some(block) = self.world.destroy(&coords) {
+                                let uv_rect = self
+                                    .world
+                                    .atlas()
+                                    .block_uv(block.kind, block::BlockFace::North);
+                                self.world.particles.break_block(block.position, uv_rect);
+                            }
                         }
                     }
                     if *button == MouseButton::Right {
                         if let Some(coords) = self.player.looking_at(&self.world) {
                             let mut modified_coords = coords;
                             modified_coords.y += 2;
-                            self.world.place(modified_coords);
+                            self.world.place(modified_coords, self.selected_block);
                         }
                     }
                 }
@@ -178,14 +326,120 @@ impl State {
         }
     }
 
+    /// The number keys select which kind of block gets placed.
+    fn block_for_key(key: VirtualKeyCode) -> Option<block::BlockKind> {
+        let index = match key {
+            VirtualKeyCode::Key1 => 0,
+            VirtualKeyCode::Key2 => 1,
+            VirtualKeyCode::Key3 => 2,
+            VirtualKeyCode::Key4 => 3,
+            VirtualKeyCode::Key5 => 4,
+            _ => return None,
+        };
+        block::BlockKind::ALL.get(index).copied()
+    }
+
     fn update(&mut self, dt: std::time::Duration) {
-        let fps = 1.0 / dt.as_secs_f32();
-        println!("FPS {:.2}", fps);
+        self.frame_times.push(dt);
+        self.reload_shaders();
+        self.player
+            .camera_mut()
+            .renderer
+            .sky
+            .time_of_day
+            .advance(dt);
         self.player.update(&mut self.camera_controller, dt);
+        self.world.update(&self.player.camera().renderer.queue, dt);
+
+        let target = self.player.target(&self.world);
+        let renderer = &mut self.player.camera_mut().renderer;
+        renderer.outline.update(&renderer.queue, target);
+        renderer.overlay.clear();
+        self.hud.draw(
+            &mut renderer.overlay,
+            self.world.atlas(),
+            self.selected_block,
+        );
+
+        if self.show_debug {
+            let stats = debug::DebugStats::collect(
+                &self.world,
+                &self.frame_times,
+                self.player.position,
+                self.player.yaw(),
+                self.player.pitch(),
+                target,
+                self.render_stats,
+            );
+            let overlay = &mut self.player.camera_mut().renderer.overlay;
+            self.hud.draw_debug(overlay, &stats, &self.frame_times);
+        } else {
+            let overlay = &mut self.player.camera_mut().renderer.overlay;
+            self.hud.draw_fps(overlay, self.frame_times.fps());
+        }
+
+        if !self.shader_errors.is_empty() {
+            let errors: Vec<&str> = self.shader_errors.values().map(String::as_str).collect();
+            let overlay = &mut self.player.camera_mut().renderer.overlay;
+            self.hud.draw_errors(overlay, &errors);
+        }
+    }
+
+    /// Rebuilds the pipelines of every shader that changed on disk since the last frame.
+    fn reload_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        for (shader, source) in watcher.changed() {
+            if self.apply_shader(shader, source) {
+                log::info!("Reloaded {}", shader.file_name());
+            }
+        }
+    }
+
+    /// Rebuilds the pipelines of `shader` from `source`. Errors are shown on screen until
+    /// the shader is fixed, and the previous pipelines stay in use meanwhile.
+    fn apply_shader(&mut self, shader: shaders::Shader, source: anyhow::Result<String>) -> bool {
+        let renderer = &mut self.player.camera_mut().renderer;
+        match source.and_then(|source| renderer.reload_shader(shader, &source)) {
+            Ok(()) => {
+                self.shader_errors.remove(&shader);
+                true
+            }
+            Err(e) => {
+                log::warn!("Could not load {}: {:#}", shader.file_name(), e);
+                self.shader_errors.insert(shader, format!("{:#}", e));
+                false
+            }
+        }
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        self.player.camera_mut().render(&self.world)
+        let camera = self.player.camera_mut();
+        let image = match self.screenshot.take() {
+            None => {
+                self.render_stats = camera.render(&self.world)?;
+                return Ok(());
+            }
+            Some(1) => camera.screenshot(&self.world),
+            Some(scale) => {
+                let image = camera.high_res_screenshot(&self.world, scale);
+                self.render_stats = camera.render(&self.world)?;
+                image
+            }
+        };
+
+        match image {
+            // Encoding a large PNG takes a while, so don't stall the game on it
+            Ok(image) => {
+                std::thread::spawn(move || match screenshot::save(&image) {
+                    Ok(path) => log::info!("Saved screenshot to {}", path.display()),
+                    Err(e) => log::warn!("Could not save screenshot: {:#}", e),
+                });
+            }
+            Err(e) => log::warn!("Could not take screenshot: {:#}", e),
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,154 @@
+//! Level of detail for far away terrain. Every chunk keeps downsampled copies of its
+//! blocks in which cells of 2x2, 4x4 or 8x8 blocks are merged into a few large boxes, and
+//! the renderer picks one of them by the distance to the camera.
+//!
+//! The merged cells are aligned to the chunk grid, so neighbouring chunks drawn at
+//! different levels neither overlap nor leave gaps. Each box is fitted to the blocks
+//! inside its cell instead of filling the whole cell, which keeps flat ground and walls
+//! in the same place at every level.
+
+use cgmath::{ElementWise, Point3, Quaternion, Vector2, Vector3, Zero};
+use std::collections::HashMap;
+
+use crate::{
+    atlas::Atlas,
+    block::{BlockFace, BlockKind},
+    world::CHUNK_SIZE,
+    Instance,
+};
+
+/// Number of downsampled levels, level `n` merges cells of `2^n` by `2^n` blocks
+pub const LOD_LEVELS: usize = 3;
+
+// Edge length of a block in world units
+const BLOCK_SIZE: i32 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LodSettings {
+    // Chunks farther away than this are not drawn, it is also the far plane
+    pub render_distance: f32,
+    // Chunks closer than this are drawn block by block, every time the distance
+    // doubles after that the next coarser level is used
+    pub detail_distance: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            render_distance: 512.0,
+            detail_distance: 128.0,
+        }
+    }
+}
+
+impl LodSettings {
+    /// Level to draw a chunk `distance` units away with, 0 is full detail.
+    /// `None` if it is beyond the render distance.
+    pub fn level(&self, distance: f32) -> Option<usize> {
+        if distance > self.render_distance {
+            return None;
+        }
+        let mut level = 0;
+        let mut limit = self.detail_distance;
+        while distance >= limit && level < LOD_LEVELS {
+            level += 1;
+            limit *= 2.0;
+        }
+        Some(level)
+    }
+}
+
+/// Horizontal distance from `position` to the closest point of `chunk`.
+pub fn chunk_distance(chunk: Vector2<i32>, position: Point3<f32>) -> f32 {
+    let min = chunk.map(|v| (v * CHUNK_SIZE) as f32);
+    let max = min.add_element_wise(CHUNK_SIZE as f32);
+    let dx = (min.x - position.x).max(position.x - max.x).max(0.0);
+    let dz = (min.y - position.z).max(position.z - max.y).max(0.0);
+    (dx * dx + dz * dz).sqrt()
+}
+
+/// A box standing in for the blocks in one or more layers of a cell of a downsampled level.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LodBlock {
+    // Corners of the box around the merged blocks in world units
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+    // Most common kind among the merged blocks
+    pub kind: BlockKind,
+}
+
+impl LodBlock {
+    pub fn to_instance(&self, atlas: &Atlas) -> Instance {
+        Instance {
+            position: (self.min + self.max) / 2.0,
+            rotation: Quaternion::zero(),
+            // The cube mesh is as large as one block
+            scale: (self.max - self.min) / BLOCK_SIZE as f32,
+            uv_rect: atlas.block_uv(self.kind, BlockFace::North),
+        }
+    }
+}
+
+/// Merges blocks into cells of `2^level` blocks along x and z. Every layer of blocks in
+/// a cell becomes a box around them, and boxes stacked directly on top of each other with
+/// the same outline and kind are merged again. Keeping the layers apart stops a thin wall
+/// from turning the ground it stands on into a solid block as tall as the wall.
+pub fn downsample(
+    blocks: impl IntoIterator<Item = (Vector3<i32>, BlockKind)>,
+    level: usize,
+) -> Vec<LodBlock> {
+    let cell_size = BLOCK_SIZE << level;
+    let mut layers: HashMap<Vector3<i32>, (LodBlock, HashMap<BlockKind, usize>)> = HashMap::new();
+    for (coords, kind) in blocks {
+        let layer = Vector3::new(
+            coords.x.div_euclid(cell_size),
+            coords.y,
+            coords.z.div_euclid(cell_size),
+        );
+        let min = coords.map(|v| (v - BLOCK_SIZE / 2) as f32);
+        let max = coords.map(|v| (v + BLOCK_SIZE / 2) as f32);
+        let (lod_block, kinds) = layers.entry(layer).or_insert_with(|| {
+            let lod_block = LodBlock { min, max, kind };
+            (lod_block, HashMap::new())
+        });
+        lod_block.min = lod_block.min.zip(min, f32::min);
+        lod_block.max = lod_block.max.zip(max, f32::max);
+        *kinds.entry(kind).or_default() += 1;
+    }
+
+    let mut layers: Vec<(Vector3<i32>, LodBlock)> = layers
+        .into_iter()
+        .map(|(layer, (mut lod_block, kinds))| {
+            // Ties go to the kind listed first so the result does not depend on hashing
+            lod_block.kind = BlockKind::ALL
+                .into_iter()
+                .rev()
+                .max_by_key(|kind| kinds.get(kind).copied().unwrap_or(0))
+                .unwrap();
+            (layer, lod_block)
+        })
+        .collect();
+    // Every column of cells from the bottom up
+    layers.sort_by_key(|(layer, _)| (layer.x, layer.z, layer.y));
+
+    let mut lod_blocks: Vec<LodBlock> = Vec::new();
+    for (_, lod_block) in layers {
+        if let Some(below) = lod_blocks.last_mut() {
+            if below.max.y == lod_block.min.y
+                && (below.min.x, below.min.z, below.max.x, below.max.z)
+                    == (
+                        lod_block.min.x,
+                        lod_block.min.z,
+                        lod_block.max.x,
+                        lod_block.max.z,
+                    )
+                && below.kind == lod_block.kind
+            {
+                below.max.y = lod_block.max.y;
+                continue;
+            }
+        }
+        lod_blocks.push(lod_block);
+    }
+    lod_blocks
+}
@@ -3,5 +3,17 @@ use tokio::runtime::Builder;
 fn main() {
     env_logger::init();
     let runtime = Builder::new_current_thread().build().unwrap();
-    runtime.block_on(tutorial12_camera::run());
+    match std::env::args().nth(1).as_deref() {
+        None => runtime.block_on(tutorial12_camera::run()),
+        // Loads every asset and lists all that are broken
+        Some("validate") => {
+            if !runtime.block_on(tutorial12_camera::validate::run()) {
+                std::process::exit(1);
+            }
+        }
+        Some(command) => {
+            eprintln!("Unknown command {}, the only one is validate", command);
+            std::process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,173 @@
+use anyhow::*;
+use image::RgbaImage;
+
+use crate::renderer::Renderer;
+
+/// A texture the renderer can draw into instead of a window surface. The frame can be
+/// copied back to the CPU afterwards, e.g. for screenshots or image comparisons.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+}
+
+impl OffscreenTarget {
+    /// Format used by headless renderers
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Creates a target matching the renderer's current size and colour format.
+    pub fn new(renderer: &Renderer) -> Self {
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: renderer.width,
+                height: renderer.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: renderer.color_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            width: renderer.width,
+            height: renderer.height,
+            format: renderer.color_format,
+        }
+    }
+
+    /// Copies the current contents of the target back into an image.
+    /// Blocks until the GPU has finished all submitted work.
+    pub fn read_image(&self, renderer: &Renderer) -> Result<RgbaImage> {
+        read_texture(
+            renderer,
+            &self.texture,
+            self.width,
+            self.height,
+            self.format,
+        )
+    }
+}
+
+/// Copies the first mip level of an 8 bit colour texture into an sRGB image. The texture
+/// needs `COPY_SRC` usage. Blocks until the GPU has finished all submitted work.
+pub fn read_texture(
+    renderer: &Renderer,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Result<RgbaImage> {
+    // Checked before anything is copied
+    pixel_layout(format)?;
+    let padded_bytes_per_row = padded_bytes_per_row(width);
+
+    let buffer = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = renderer
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    renderer.queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    renderer.device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let image = image_from_rows(&slice.get_mapped_range(), width, height, format);
+    buffer.unmap();
+    image
+}
+
+/// Bytes per row of a texture to buffer copy of `width` 8 bit RGBA texels, rows have to
+/// be aligned to 256 bytes.
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(align) * align
+}
+
+// Whether the channels are in BGRA order and whether the texels are sRGB encoded
+fn pixel_layout(format: wgpu::TextureFormat) -> Result<(bool, bool)> {
+    Ok(match format {
+        wgpu::TextureFormat::Rgba8UnormSrgb => (false, true),
+        wgpu::TextureFormat::Bgra8UnormSrgb => (true, true),
+        wgpu::TextureFormat::Rgba8Unorm => (false, false),
+        wgpu::TextureFormat::Bgra8Unorm => (true, false),
+        _ => bail!("Reading back {:?} textures is not supported", format),
+    })
+}
+
+/// Turns the rows of a texture copied into a buffer, padded to [`padded_bytes_per_row`],
+/// into an sRGB image.
+pub fn image_from_rows(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Result<RgbaImage> {
+    let (bgra, srgb) = pixel_layout(format)?;
+    let unpadded_bytes_per_row = width as usize * 4;
+    let padded_bytes_per_row = padded_bytes_per_row(width) as usize;
+    if data.len() < padded_bytes_per_row * height as usize {
+        bail!("Readback buffer does not match the texture size");
+    }
+    let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * height as usize);
+    for row in data.chunks(padded_bytes_per_row).take(height as usize) {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+    }
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        if bgra {
+            pixel.swap(0, 2);
+        }
+        // Shaders write linear colours, a linear surface stores them without encoding
+        // so they have to be encoded here for the PNG to look the way it was meant to
+        if !srgb {
+            for channel in &mut pixel[..3] {
+                let linear = *channel as f32 / 255.0;
+                *channel = (crate::texture::linear_to_srgb(linear) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    RgbaImage::from_raw(width, height, pixels).context("Could not create the image")
+}
@@ -0,0 +1,269 @@
+use cgmath::{Deg, Matrix4, SquareMatrix, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{bind_group_layouts, block::BlockFace, vertex_layouts};
+
+// Slightly larger than a block so the lines are not hidden inside its faces
+const OUTLINE_SCALE: f32 = 1.005;
+
+#[rustfmt::skip]
+const CUBE_EDGES: [[f32; 3]; 24] = [
+    // Bottom
+    [-1.0, -1.0, -1.0], [1.0, -1.0, -1.0],
+    [1.0, -1.0, -1.0], [1.0, -1.0, 1.0],
+    [1.0, -1.0, 1.0], [-1.0, -1.0, 1.0],
+    [-1.0, -1.0, 1.0], [-1.0, -1.0, -1.0],
+    // Top
+    [-1.0, 1.0, -1.0], [1.0, 1.0, -1.0],
+    [1.0, 1.0, -1.0], [1.0, 1.0, 1.0],
+    [1.0, 1.0, 1.0], [-1.0, 1.0, 1.0],
+    [-1.0, 1.0, 1.0], [-1.0, 1.0, -1.0],
+    // Sides
+    [-1.0, -1.0, -1.0], [-1.0, 1.0, -1.0],
+    [1.0, -1.0, -1.0], [1.0, 1.0, -1.0],
+    [1.0, -1.0, 1.0], [1.0, 1.0, 1.0],
+    [-1.0, -1.0, 1.0], [-1.0, 1.0, 1.0],
+];
+
+// The top face, rotated onto whichever face is highlighted
+#[rustfmt::skip]
+const TOP_FACE: [[f32; 3]; 6] = [
+    [-1.0, 1.0, -1.0], [-1.0, 1.0, 1.0], [1.0, 1.0, 1.0],
+    [-1.0, 1.0, -1.0], [1.0, 1.0, 1.0], [1.0, 1.0, -1.0],
+];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineUniform {
+    box_model: [[f32; 4]; 4],
+    face_model: [[f32; 4]; 4],
+    line_color: [f32; 4],
+    face_color: [f32; 4],
+}
+
+/// Wireframe around the block the player is aiming at, with the face the view ray
+/// enters through highlighted. Drawn on top of the world in the main pass.
+pub struct Outline {
+    target: Option<(Vector3<i32>, BlockFace)>,
+    uniform: OutlineUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    edge_buffer: wgpu::Buffer,
+    face_buffer: wgpu::Buffer,
+    pipeline_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    line_pipeline: wgpu::RenderPipeline,
+    face_pipeline: wgpu::RenderPipeline,
+}
+
+impl Outline {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform = OutlineUniform {
+            box_model: Matrix4::identity().into(),
+            face_model: Matrix4::identity().into(),
+            line_color: [0.0, 0.0, 0.0, 0.7],
+            face_color: [1.0, 1.0, 1.0, 0.2],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &bind_group_layouts::OUTLINE_ENTRIES,
+            label: Some("outline_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("outline_bind_group"),
+        });
+
+        let edge_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Edge Buffer"),
+            contents: bytemuck::cast_slice(&CUBE_EDGES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let face_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Face Buffer"),
+            contents: bytemuck::cast_slice(&TOP_FACE),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let (line_pipeline, face_pipeline) = Outline::create_pipelines(
+            device,
+            &pipeline_layout,
+            color_format,
+            include_str!("outline.wgsl"),
+        );
+
+        Self {
+            target: None,
+            uniform,
+            uniform_buffer,
+            bind_group,
+            edge_buffer,
+            face_buffer,
+            pipeline_layout,
+            color_format,
+            line_pipeline,
+            face_pipeline,
+        }
+    }
+
+    /// Replaces both pipelines with ones built from `source`, keeping the old ones on errors.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> anyhow::Result<()> {
+        let (line_pipeline, face_pipeline) = crate::shaders::try_create(device, || {
+            Outline::create_pipelines(device, &self.pipeline_layout, self.color_format, source)
+        })?;
+        self.line_pipeline = line_pipeline;
+        self.face_pipeline = face_pipeline;
+        Ok(())
+    }
+
+    /// Builds the line and face pipelines, which share one shader module.
+    fn create_pipelines(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        source: &str,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let line_pipeline = Outline::create_pipeline(
+            device,
+            layout,
+            &shader,
+            color_format,
+            ("vs_line", "fs_line"),
+            wgpu::PrimitiveTopology::LineList,
+        );
+        let face_pipeline = Outline::create_pipeline(
+            device,
+            layout,
+            &shader,
+            color_format,
+            ("vs_face", "fs_face"),
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+        (line_pipeline, face_pipeline)
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        color_format: wgpu::TextureFormat,
+        (vertex_entry_point, fragment_entry_point): (&str, &str),
+        topology: wgpu::PrimitiveTopology,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vertex_entry_point,
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &vertex_layouts::OUTLINE_VERTEX_ATTRIBUTES,
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology,
+                ..Default::default()
+            },
+            // Tested against the world so hidden edges stay hidden, but never written
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: crate::texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Moves the outline to the given block and face, `None` hides it.
+    pub fn update(&mut self, queue: &wgpu::Queue, target: Option<(Vector3<i32>, BlockFace)>) {
+        self.target = target;
+        let Some((coords, face)) = target else {
+            return;
+        };
+
+        let translation = Matrix4::from_translation(coords.map(|v| v as f32));
+        let scale = Matrix4::from_scale(OUTLINE_SCALE);
+        let rotation = match face {
+            BlockFace::Top => Matrix4::identity(),
+            BlockFace::Bottom => Matrix4::from_angle_x(Deg(180.0)),
+            BlockFace::North => Matrix4::from_angle_x(Deg(-90.0)),
+            BlockFace::South => Matrix4::from_angle_x(Deg(90.0)),
+            BlockFace::East => Matrix4::from_angle_z(Deg(-90.0)),
+            BlockFace::West => Matrix4::from_angle_z(Deg(90.0)),
+        };
+        self.uniform.box_model = (translation * scale).into();
+        self.uniform.face_model = (translation * rotation * scale).into();
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.target.is_some()
+    }
+
+    pub fn buffer_memory(&self) -> u64 {
+        self.uniform_buffer.size() + self.edge_buffer.size() + self.face_buffer.size()
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if !self.is_visible() {
+            return;
+        }
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+
+        render_pass.set_pipeline(&self.face_pipeline);
+        render_pass.set_vertex_buffer(0, self.face_buffer.slice(..));
+        render_pass.draw(0..TOP_FACE.len() as u32, 0..1);
+
+        render_pass.set_pipeline(&self.line_pipeline);
+        render_pass.set_vertex_buffer(0, self.edge_buffer.slice(..));
+        render_pass.draw(0..CUBE_EDGES.len() as u32, 0..1);
+    }
+}
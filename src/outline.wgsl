@@ -0,0 +1,37 @@
+// Outline around the targeted block and a highlight on the face being looked at
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Outline {
+    box_model: mat4x4<f32>,
+    face_model: mat4x4<f32>,
+    line_color: vec4<f32>,
+    face_color: vec4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> outline: Outline;
+
+@vertex
+fn vs_line(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * outline.box_model * vec4<f32>(position, 1.0);
+}
+
+@fragment
+fn fs_line() -> @location(0) vec4<f32> {
+    return outline.line_color;
+}
+
+@vertex
+fn vs_face(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * outline.face_model * vec4<f32>(position, 1.0);
+}
+
+@fragment
+fn fs_face() -> @location(0) vec4<f32> {
+    return outline.face_color;
+}
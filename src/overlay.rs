@@ -0,0 +1,342 @@
+use image::RgbaImage;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+
+use crate::{bind_group_layouts, texture::Texture, vertex_layouts};
+
+/// Handle to a texture registered with [`Overlay::add_texture`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpriteTexture(usize);
+
+/// Rectangle in pixels, the origin is the top left corner of the screen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Rectangle of the given size centred on a point.
+    pub fn centered(center_x: f32, center_y: f32, width: f32, height: f32) -> Self {
+        Self::new(
+            center_x - width / 2.0,
+            center_y - height / 2.0,
+            width,
+            height,
+        )
+    }
+
+    /// Shrinks the rectangle by `amount` on every side.
+    pub fn inset(&self, amount: f32) -> Self {
+        Self::new(
+            self.x + amount,
+            self.y + amount,
+            self.width - 2.0 * amount,
+            self.height - 2.0 * amount,
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl SpriteVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &vertex_layouts::SPRITE_VERTEX_ATTRIBUTES,
+        }
+    }
+}
+
+/// Batches screen space quads and draws them on top of the finished frame. Quads are
+/// queued every frame after [`Overlay::clear`] and drawn in the order they were added,
+/// consecutive quads with the same texture share a draw call.
+pub struct Overlay {
+    width: u32,
+    height: u32,
+    projection_buffer: wgpu::Buffer,
+    projection_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    textures: Vec<(Texture, wgpu::BindGroup)>,
+    vertices: Vec<SpriteVertex>,
+    batches: Vec<(SpriteTexture, Range<u32>)>,
+    pipeline_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Overlay {
+    /// A single white pixel, used for solid rectangles
+    pub const WHITE: SpriteTexture = SpriteTexture(0);
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let projection_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Projection Buffer"),
+            contents: bytemuck::cast_slice(&Overlay::projection(width, height)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let projection_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &bind_group_layouts::OVERLAY_PROJECTION_ENTRIES,
+                label: Some("overlay_projection_bind_group_layout"),
+            });
+        let projection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &projection_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: projection_buffer.as_entire_binding(),
+            }],
+            label: Some("overlay_projection_bind_group"),
+        });
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &bind_group_layouts::OVERLAY_TEXTURE_ENTRIES,
+                label: Some("overlay_texture_bind_group_layout"),
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[&projection_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Overlay::create_pipeline(
+            device,
+            &pipeline_layout,
+            color_format,
+            include_str!("overlay.wgsl"),
+        );
+
+        let mut overlay = Self {
+            width,
+            height,
+            projection_buffer,
+            projection_bind_group,
+            texture_bind_group_layout,
+            textures: Vec::new(),
+            vertices: Vec::new(),
+            batches: Vec::new(),
+            pipeline_layout,
+            color_format,
+            pipeline,
+        };
+        let white = RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        // A single mip level is always a valid texture
+        overlay
+            .add_texture(device, queue, &[white], "overlay_white")
+            .unwrap();
+        overlay
+    }
+
+    /// Replaces the pipeline with one built from `source`, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> anyhow::Result<()> {
+        self.pipeline = crate::shaders::try_create(device, || {
+            Overlay::create_pipeline(device, &self.pipeline_layout, self.color_format, source)
+        })?;
+        Ok(())
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        source: &str,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SpriteVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Always on top, so the overlay pass has no depth buffer
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Maps pixel coordinates with the origin in the top left corner to clip space.
+    fn projection(width: u32, height: u32) -> [[f32; 4]; 4] {
+        let ortho = cgmath::ortho(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+        (crate::camera::OPENGL_TO_WGPU_MATRIX * ortho).into()
+    }
+
+    /// Makes a texture available for sprites. `mips` are the levels of the texture
+    /// starting with the full size image, like for [`Texture::from_mip_chain`].
+    pub fn add_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mips: &[RgbaImage],
+        label: &str,
+    ) -> anyhow::Result<SpriteTexture> {
+        let texture = Texture::from_mip_chain(device, queue, mips, Some(label), false)?;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some(label),
+        });
+        self.textures.push((texture, bind_group));
+        Ok(SpriteTexture(self.textures.len() - 1))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        queue.write_buffer(
+            &self.projection_buffer,
+            0,
+            bytemuck::cast_slice(&Overlay::projection(width, height)),
+        );
+    }
+
+    /// Drops everything queued for the previous frame.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.batches.clear();
+    }
+
+    /// Queues a solid rectangle, `color` is linear RGBA.
+    pub fn rect(&mut self, rect: Rect, color: [f32; 4]) {
+        self.sprite(Overlay::WHITE, rect, [0.0, 0.0, 1.0, 1.0], color);
+    }
+
+    /// Queues the `uv` region ([min_u, min_v, max_u, max_v]) of `texture` stretched over
+    /// `rect`. The texture colour is multiplied with `color`.
+    pub fn sprite(&mut self, texture: SpriteTexture, rect: Rect, uv: [f32; 4], color: [f32; 4]) {
+        let (left, top) = (rect.x, rect.y);
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        let vertex = |position: [f32; 2], tex_coords: [f32; 2]| SpriteVertex {
+            position,
+            tex_coords,
+            color,
+        };
+        let start = self.vertices.len() as u32;
+        self.vertices.extend([
+            vertex([left, top], [uv[0], uv[1]]),
+            vertex([left, bottom], [uv[0], uv[3]]),
+            vertex([right, bottom], [uv[2], uv[3]]),
+            vertex([left, top], [uv[0], uv[1]]),
+            vertex([right, bottom], [uv[2], uv[3]]),
+            vertex([right, top], [uv[2], uv[1]]),
+        ]);
+        let end = self.vertices.len() as u32;
+
+        match self.batches.last_mut() {
+            Some((last, range)) if *last == texture => range.end = end,
+            _ => self.batches.push((texture, start..end)),
+        }
+    }
+
+    /// Number of triangles in each draw call [`Overlay::render`] will make.
+    pub fn batch_triangles(&self) -> impl Iterator<Item = u64> + '_ {
+        self.batches
+            .iter()
+            .map(|(_, vertices)| vertices.len() as u64 / 3)
+    }
+
+    /// Bytes in the projection buffer and the vertex buffer for the queued quads.
+    pub fn buffer_memory(&self) -> u64 {
+        self.projection_buffer.size()
+            + (self.vertices.len() * std::mem::size_of::<SpriteVertex>()) as u64
+    }
+
+    /// Draws the queued quads over whatever is already in `view`.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Vertex Buffer"),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.projection_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        for (texture, vertices) in &self.batches {
+            render_pass.set_bind_group(1, &self.textures[texture.0].1, &[]);
+            render_pass.draw(vertices.clone(), 0..1);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Camera facing quads for particles, textured from the block atlas or drawn as soft dots
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+struct Shadow {
+    cascades: array<mat4x4<f32>, 4>,
+    splits: vec4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    bias: vec4<f32>,
+}
+@group(2) @binding(1)
+var<uniform> shadow: Shadow;
+
+struct Sky {
+    inv_view_proj: mat4x4<f32>,
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    sun_direction: vec4<f32>,
+    // x: fog start, y: fog end
+    fog: vec4<f32>,
+}
+@group(2) @binding(4)
+var<uniform> sky: Sky;
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct ParticleInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) uv_rect: vec4<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) glow: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    // From -1 to 1 across the quad
+    @location(1) corner: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) world_position: vec3<f32>,
+    @location(4) glow: f32,
+    @location(5) textured: f32,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    particle: ParticleInput,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    // Turned towards the camera around the particle's centre, upright where possible
+    let to_camera = normalize(camera.view_pos.xyz - particle.position);
+    var right = cross(vec3<f32>(0.0, 1.0, 0.0), to_camera);
+    if length(right) < 0.001 {
+        right = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    let up = cross(to_camera, right);
+    let world_position = particle.position
+        + (right * corner.x + up * corner.y) * particle.size * 0.5;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    let uv = vec2<f32>(corner.x * 0.5 + 0.5, 0.5 - corner.y * 0.5);
+    out.tex_coords = mix(particle.uv_rect.xy, particle.uv_rect.zw, uv);
+    out.corner = corner;
+    out.color = particle.color;
+    out.world_position = world_position;
+    out.glow = particle.glow;
+    out.textured = select(0.0, 1.0, particle.uv_rect.z > particle.uv_rect.x);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampled either way, texture sampling has to happen in uniform control flow
+    let texel = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.corner));
+    let shape = select(vec4<f32>(1.0, 1.0, 1.0, falloff), texel, in.textured > 0.5);
+    let color = in.color * shape;
+    if color.a < 0.01 {
+        discard;
+    }
+
+    // No normal to shade with, so only the ambient light and the sun or moon
+    let lighting = light.color * 0.1 + shadow.sun_color.xyz * 0.8;
+    let lit = color.rgb * mix(lighting, vec3<f32>(1.0), in.glow);
+
+    let distance = length(in.world_position - camera.view_pos.xyz);
+    let fog = smoothstep(sky.fog.x, sky.fog.y, distance);
+    return vec4<f32>(mix(lit, sky.horizon_color.xyz, fog), color.a);
+}
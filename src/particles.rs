@@ -0,0 +1,331 @@
+//! Small camera facing quads for broken blocks, smoke, rain and flames. Particles are
+//! simulated on the CPU and drawn with one instance each. Every random choice comes from a
+//! seeded generator, so the same seed and time steps always give the same particles.
+
+use cgmath::Vector3;
+use std::{ops::Range, time::Duration};
+
+/// Particles alive at once, nothing new spawns while there are this many
+pub const MAX_PARTICLES: usize = 8192;
+
+// A broken block falls apart into this many pieces along every axis
+const BREAK_PIECES: usize = 4;
+// Fraction of the horizontal velocity kept after sliding over the ground for a second
+const GROUND_FRICTION: f32 = 0.002;
+
+/// xorshift64*, small and fast and gives the same numbers on every platform.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Scrambled with splitmix64 so that nearby seeds do not start out alike
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        // The state must never be zero
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniformly distributed in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, range: Range<f32>) -> f32 {
+        range.start + (range.end - range.start) * self.next_f32()
+    }
+
+    /// A vector with every component between `-spread` and `spread` along its axis.
+    pub fn spread(&mut self, spread: Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(
+            (self.next_f32() * 2.0 - 1.0) * spread.x,
+            (self.next_f32() * 2.0 - 1.0) * spread.y,
+            (self.next_f32() * 2.0 - 1.0) * spread.z,
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Particle {
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    // Seconds since it was spawned, it disappears once this reaches the lifetime
+    pub age: f32,
+    pub lifetime: f32,
+    // Edge length of the quad in world units
+    pub size: f32,
+    // Added to the vertical velocity every second, negative pulls it down
+    pub gravity: f32,
+    // Region of the block atlas shown as [min_u, min_v, max_u, max_v],
+    // an empty region draws a soft round dot instead
+    pub uv_rect: [f32; 4],
+    // Multiplied with the texture
+    pub color: [f32; 4],
+    // 0 is lit like the blocks around it, 1 always shines at full brightness
+    pub glow: f32,
+    // Becomes transparent over its lifetime instead of vanishing all at once
+    pub fade: bool,
+    // Disappears when it hits a block instead of coming to rest on it
+    pub vanish_on_impact: bool,
+}
+
+impl Particle {
+    pub fn is_alive(&self) -> bool {
+        self.age < self.lifetime
+    }
+
+    /// The colour to draw it with right now, including the fading.
+    pub fn current_color(&self) -> [f32; 4] {
+        let [r, g, b, a] = self.color;
+        if self.fade {
+            [r, g, b, a * (1.0 - self.age / self.lifetime).max(0.0)]
+        } else {
+            self.color
+        }
+    }
+}
+
+/// Describes the particles an effect is made of. Added to a [`ParticleSystem`] it spawns
+/// them continuously, e.g. smoke from a chimney or rain around the player, and
+/// [`ParticleSystem::burst`] spawns many at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emitter {
+    pub position: Vector3<f32>,
+    // Particles start up to this far from the position along every axis
+    pub area: Vector3<f32>,
+    // Particles spawned per second
+    pub rate: f32,
+    pub velocity: Vector3<f32>,
+    // Largest random change of the starting velocity along every axis
+    pub velocity_spread: Vector3<f32>,
+    pub lifetime: Range<f32>,
+    pub size: Range<f32>,
+    pub gravity: f32,
+    pub uv_rect: [f32; 4],
+    pub color: [f32; 4],
+    pub glow: f32,
+    pub fade: bool,
+    pub vanish_on_impact: bool,
+}
+
+impl Emitter {
+    /// Grey puffs that slowly rise and fade away.
+    pub fn smoke(position: Vector3<f32>) -> Self {
+        Self {
+            position,
+            area: Vector3::new(0.3, 0.1, 0.3),
+            rate: 6.0,
+            velocity: Vector3::new(0.0, 1.5, 0.0),
+            velocity_spread: Vector3::new(0.3, 0.3, 0.3),
+            lifetime: 2.0..4.0,
+            size: 0.5..0.9,
+            gravity: 0.2,
+            uv_rect: [0.0; 4],
+            color: [0.35, 0.35, 0.35, 0.6],
+            glow: 0.0,
+            fade: true,
+            vanish_on_impact: false,
+        }
+    }
+
+    /// Drops falling from a square `2 * radius` wide centred above `position`.
+    pub fn rain(position: Vector3<f32>, radius: f32) -> Self {
+        Self {
+            position,
+            area: Vector3::new(radius, 0.0, radius),
+            // The same density however large the area is
+            rate: radius * radius * 0.5,
+            velocity: Vector3::new(0.0, -24.0, 0.0),
+            velocity_spread: Vector3::new(0.0, 2.0, 0.0),
+            lifetime: 3.0..3.0,
+            size: 0.08..0.12,
+            gravity: 0.0,
+            uv_rect: [0.0; 4],
+            color: [0.55, 0.65, 0.9, 0.7],
+            glow: 0.0,
+            fade: false,
+            vanish_on_impact: true,
+        }
+    }
+
+    /// Small glowing flames for the tip of a torch. A smoke emitter a little above
+    /// the same position makes it smoke as well.
+    pub fn torch(position: Vector3<f32>) -> Self {
+        Self {
+            position,
+            area: Vector3::new(0.1, 0.05, 0.1),
+            rate: 10.0,
+            velocity: Vector3::new(0.0, 0.6, 0.0),
+            velocity_spread: Vector3::new(0.1, 0.2, 0.1),
+            lifetime: 0.3..0.6,
+            size: 0.15..0.3,
+            gravity: 0.5,
+            uv_rect: [0.0; 4],
+            color: [1.0, 0.6, 0.15, 0.9],
+            glow: 1.0,
+            fade: true,
+            vanish_on_impact: false,
+        }
+    }
+
+    fn spawn(&self, rng: &mut Rng) -> Particle {
+        Particle {
+            position: self.position + rng.spread(self.area),
+            velocity: self.velocity + rng.spread(self.velocity_spread),
+            age: 0.0,
+            lifetime: rng.range(self.lifetime.clone()),
+            size: rng.range(self.size.clone()),
+            gravity: self.gravity,
+            uv_rect: self.uv_rect,
+            color: self.color,
+            glow: self.glow,
+            fade: self.fade,
+            vanish_on_impact: self.vanish_on_impact,
+        }
+    }
+}
+
+/// Identifies an emitter added to a [`ParticleSystem`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EmitterId(u32);
+
+pub struct ParticleSystem {
+    rng: Rng,
+    particles: Vec<Particle>,
+    // Every emitter with the fraction of a particle it still owes from earlier steps
+    emitters: Vec<(EmitterId, Emitter, f32)>,
+    next_emitter: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            particles: Vec::new(),
+            emitters: Vec::new(),
+            next_emitter: 0,
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) -> EmitterId {
+        let id = EmitterId(self.next_emitter);
+        self.next_emitter += 1;
+        self.emitters.push((id, emitter, 0.0));
+        id
+    }
+
+    /// Stops the emitter, the particles it already spawned live on.
+    pub fn remove_emitter(&mut self, id: EmitterId) -> Option<Emitter> {
+        let index = self.emitters.iter().position(|(e, _, _)| *e == id)?;
+        Some(self.emitters.remove(index).1)
+    }
+
+    /// For moving an emitter around, e.g. to keep rain above the player.
+    pub fn emitter_mut(&mut self, id: EmitterId) -> Option<&mut Emitter> {
+        self.emitters
+            .iter_mut()
+            .find(|(e, _, _)| *e == id)
+            .map(|(_, emitter, _)| emitter)
+    }
+
+    pub fn spawn(&mut self, particle: Particle) {
+        if self.particles.len() < MAX_PARTICLES {
+            self.particles.push(particle);
+        }
+    }
+
+    /// Spawns `count` particles from `emitter` at once.
+    pub fn burst(&mut self, emitter: &Emitter, count: usize) {
+        for _ in 0..count {
+            let particle = emitter.spawn(&mut self.rng);
+            self.spawn(particle);
+        }
+    }
+
+    /// Breaks the block centred on `position` into small pieces flying apart, each
+    /// showing a random corner of its texture given by `uv_rect`.
+    pub fn break_block(&mut self, position: Vector3<f32>, uv_rect: [f32; 4]) {
+        let [min_u, min_v, max_u, max_v] = uv_rect;
+        // Every piece shows a quarter of the texture along each side
+        let (piece_u, piece_v) = ((max_u - min_u) / 4.0, (max_v - min_v) / 4.0);
+        for x in 0..BREAK_PIECES {
+            for y in 0..BREAK_PIECES {
+                for z in 0..BREAK_PIECES {
+                    // From -1 to 1 across the block, which is two units wide
+                    let offset = Vector3::new(x, y, z)
+                        .map(|v| (v as f32 + 0.5) / BREAK_PIECES as f32 * 2.0 - 1.0);
+                    let u = min_u + (max_u - min_u - piece_u) * self.rng.next_f32();
+                    let v = min_v + (max_v - min_v - piece_v) * self.rng.next_f32();
+                    let particle = Particle {
+                        position: position + offset,
+                        velocity: offset * 3.0
+                            + self.rng.spread(Vector3::new(1.5, 1.5, 1.5))
+                            + Vector3::new(0.0, 4.0, 0.0),
+                        age: 0.0,
+                        lifetime: self.rng.range(0.4..1.2),
+                        size: self.rng.range(0.2..0.35),
+                        gravity: -30.0,
+                        uv_rect: [u, v, u + piece_u, v + piece_v],
+                        color: [1.0; 4],
+                        glow: 0.0,
+                        fade: false,
+                        vanish_on_impact: false,
+                    };
+                    self.spawn(particle);
+                }
+            }
+        }
+    }
+
+    /// Advances the simulation by `dt`. Emitters spawn their share of particles, and every
+    /// particle moves, falls and ages. `is_solid` tells whether a point is inside a block,
+    /// particles stop moving along any axis that would take them into one.
+    pub fn update(&mut self, dt: Duration, is_solid: impl Fn(Vector3<f32>) -> bool) {
+        let dt = dt.as_secs_f32();
+        for (_, emitter, owed) in &mut self.emitters {
+            *owed += emitter.rate * dt;
+            while *owed >= 1.0 {
+                *owed -= 1.0;
+                if self.particles.len() < MAX_PARTICLES {
+                    self.particles.push(emitter.spawn(&mut self.rng));
+                }
+            }
+        }
+
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity.y += particle.gravity * dt;
+            // One axis at a time so that pieces landing on the ground still slide along it
+            for axis in 0..3 {
+                let mut moved = particle.position;
+                moved[axis] += particle.velocity[axis] * dt;
+                if !is_solid(moved) {
+                    particle.position = moved;
+                    continue;
+                }
+                if particle.vanish_on_impact {
+                    particle.age = particle.lifetime;
+                }
+                particle.velocity[axis] = 0.0;
+                if axis == 1 {
+                    let friction = GROUND_FRICTION.powf(dt);
+                    particle.velocity.x *= friction;
+                    particle.velocity.z *= friction;
+                }
+            }
+        }
+        self.particles.retain(Particle::is_alive);
+    }
+}
@@ -2,6 +2,8 @@ use cgmath::*;
 use std::{f32::consts::FRAC_PI_2, ops::Add};
 use winit::{dpi::PhysicalPosition, event::*};
 
+use crate::block::BlockFace;
+
 const PITCH_CLAMP: Rad<f32> = Rad(FRAC_PI_2 - 0.0001);
 
 pub struct Player {
@@ -95,10 +97,15 @@ impl Player {
         self.pitch = Rad(self.pitch.0.clamp(-PITCH_CLAMP.0, PITCH_CLAMP.0));
     }
 
-    pub fn looking_at(&self, world: &crate::world::World) -> Option<Vector3<i32>> {
+    /// Unit vector in the direction the player is looking.
+    fn forward(&self) -> Vector3<f32> {
         let (pitch_sin, pitch_cos) = self.pitch().sin_cos();
         let (yaw_sin, yaw_cos) = self.yaw().sin_cos();
-        let forward = Vector3::new(yaw_cos * pitch_cos, pitch_sin, yaw_sin * pitch_cos).normalize();
+        Vector3::new(yaw_cos * pitch_cos, pitch_sin, yaw_sin * pitch_cos).normalize()
+    }
+
+    pub fn looking_at(&self, world: &crate::world::World) -> Option<Vector3<i32>> {
+        let forward = self.forward();
         let player_position = self.position;
         let player_radius = 8;
 
@@ -107,12 +114,41 @@ impl Player {
             ray_coords = ray_coords.add(forward);
             let pos = ray_coords.map(|value| value.round() as i32);
             if world.blocks().contains_key(&pos) {
-                println!("found block");
                 return Some(pos);
             }
         }
         None
     }
+
+    /// The block the player is looking at together with the face the view ray enters it through.
+    pub fn target(&self, world: &crate::world::World) -> Option<(Vector3<i32>, BlockFace)> {
+        let coords = self.looking_at(world)?;
+        let forward = self.forward();
+        // Ray origin relative to the centre of the block, which spans -1..1 on every axis
+        let origin = self.position.to_vec() - coords.map(|v| v as f32);
+
+        // The ray enters the block through the face whose plane it crosses last
+        let mut entry = (f32::NEG_INFINITY, BlockFace::Top);
+        for (axis, negative, positive) in [
+            (0, BlockFace::West, BlockFace::East),
+            (1, BlockFace::Bottom, BlockFace::Top),
+            (2, BlockFace::North, BlockFace::South),
+        ] {
+            if forward[axis] == 0.0 {
+                continue;
+            }
+            let (face, plane) = if forward[axis] > 0.0 {
+                (negative, -1.0)
+            } else {
+                (positive, 1.0)
+            };
+            let distance = (plane - origin[axis]) / forward[axis];
+            if distance > entry.0 {
+                entry = (distance, face);
+            }
+        }
+        Some((coords, entry.1))
+    }
 }
 
 #[derive(Debug)]
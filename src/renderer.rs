@@ -0,0 +1,1061 @@
+use crate::{
+    bind_group_layouts::{self, PALETTE_SIZE},
+    block::{BlockKind, BlockLayer},
+    camera::{view_matrix, CameraUniform, Projection},
+    lod::{LodSettings, LOD_LEVELS},
+    model::{DrawLight, DrawModel, Vertex},
+    shaders::{self, Shader},
+    skeleton::MAX_JOINTS,
+    world::{block_at, chunk_of, section_of, CHUNK_SIZE},
+};
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Point3, Rad, Vector3};
+use std::{iter, sync::Arc};
+use wgpu::util::DeviceExt;
+
+/// Everything needed to draw the world into a texture view. It does not know where the
+/// frames end up, so it works the same for a window surface and for an offscreen texture.
+pub struct Renderer {
+    // Shared with the threads that load assets in the background
+    pub device: Arc<wgpu::Device>, // Adapter to our graphics card
+    pub queue: Arc<wgpu::Queue>,
+    pub color_format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    // Camera configuration
+    camera_uniform: CameraUniform,
+    camera_projection: Projection,
+    camera_buffer: wgpu::Buffer,
+    lod_settings: LodSettings,
+    // Lighting
+    light_uniform: crate::LightUniform,
+    light_buffer: wgpu::Buffer,
+    pub shadow_map: crate::shadow::ShadowMap,
+    pub sky: crate::sky::Sky,
+    pub outline: crate::outline::Outline,
+    pub overlay: crate::overlay::Overlay,
+    // Rendering
+    block_pipeline_layout: wgpu::PipelineLayout,
+    skinned_pipeline_layout: wgpu::PipelineLayout,
+    light_pipeline_layout: wgpu::PipelineLayout,
+    render_pipeline: wgpu::RenderPipeline,
+    cutout_render_pipeline: wgpu::RenderPipeline,
+    translucent_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
+    light_render_pipeline: wgpu::RenderPipeline,
+    particle_render_pipeline: wgpu::RenderPipeline,
+    depth_map: crate::texture::Texture,
+    // Bind groups
+    camera_bind_group: wgpu::BindGroup,
+    light_bind_group: wgpu::BindGroup,
+    pub texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    skin_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+struct CameraBindings {
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    camera_bind_group: wgpu::BindGroup,
+    camera_buffer: wgpu::Buffer,
+}
+
+struct LightBindings {
+    light_bind_group_layout: wgpu::BindGroupLayout,
+    light_bind_group: wgpu::BindGroup,
+    light_buffer: wgpu::Buffer,
+}
+
+struct BlockPipelines {
+    render_pipeline: wgpu::RenderPipeline,
+    cutout_render_pipeline: wgpu::RenderPipeline,
+    translucent_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
+}
+
+/// The parts of a pipeline that differ between the opaque, cutout and translucent passes.
+struct PipelineOptions {
+    vertex_entry_point: &'static str,
+    fragment_entry_point: &'static str,
+    blend: wgpu::BlendState,
+    depth_write_enabled: bool,
+    cull_mode: Option<wgpu::Face>,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        Self {
+            vertex_entry_point: "vs_main",
+            fragment_entry_point: "fs_main",
+            blend: wgpu::BlendState {
+                alpha: wgpu::BlendComponent::REPLACE,
+                color: wgpu::BlendComponent::REPLACE,
+            },
+            depth_write_enabled: true,
+            cull_mode: Some(wgpu::Face::Back),
+        }
+    }
+}
+
+/// What went into the last frame, shown on the debug screen.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    // Bytes in all GPU buffers the frame used
+    pub buffer_memory: u64,
+    // Chunks drawn at every level of detail, full detail first
+    pub chunks: [u32; LOD_LEVELS + 1],
+    // Blocks and boxes left out because no open path leads from the camera to them
+    pub occluded: u32,
+    pub entities: u32,
+    pub particles: u32,
+}
+
+impl RenderStats {
+    fn record_draw(&mut self, triangles: u64) {
+        self.draw_calls += 1;
+        self.triangles += triangles;
+    }
+
+    fn record_model(&mut self, model: &crate::model::Model, instances: u32) {
+        for mesh in &model.meshes {
+            let triangles = mesh.num_elements as u64 / 3;
+            if mesh.skin_buffer.is_some() {
+                // Skinned instances are drawn one by one with their own joint matrices
+                for _ in 0..instances {
+                    self.record_draw(triangles);
+                }
+            } else {
+                self.record_draw(triangles * instances as u64);
+            }
+        }
+    }
+}
+
+/// Opens a device on `adapter` with the features and limits the renderer relies on.
+pub async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None, // Trace path
+        )
+        .await?;
+    Ok((device, queue))
+}
+
+impl Renderer {
+    pub fn new(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        color_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let depth_map =
+            crate::texture::Texture::create_depth_texture(&device, width, height, "depth_texture");
+
+        let lod_settings = LodSettings::default();
+        let camera_projection =
+            Projection::new(width, height, Deg(45.0), 0.1, lod_settings.render_distance);
+        let camera_uniform = CameraUniform::new();
+        let light_uniform = crate::LightUniform {
+            position: [0.0, 10.0, 0.0],
+            _padding: 0,
+            color: [1.0, 1.0, 1.0],
+            _padding2: 0,
+        };
+
+        let texture_bind_group_layout = Renderer::create_texture_bindings(&device);
+        let skin_bind_group_layout = Renderer::create_skin_bindings(&device);
+        let camera_bindings = Renderer::create_camera_bindings(&device, camera_uniform);
+        let shadow_map = crate::shadow::ShadowMap::new(
+            &device,
+            crate::shadow::ShadowSettings::default(),
+            &skin_bind_group_layout,
+        );
+        let sky = crate::sky::Sky::new(&device, color_format);
+        let light_bindings =
+            Renderer::create_light_bindings(&device, light_uniform, &shadow_map, &sky);
+        let outline = crate::outline::Outline::new(
+            &device,
+            color_format,
+            &camera_bindings.camera_bind_group_layout,
+        );
+        let overlay = crate::overlay::Overlay::new(&device, &queue, color_format, width, height);
+        let (block_pipeline_layout, skinned_pipeline_layout, light_pipeline_layout) =
+            Renderer::complete_bindings(
+                &device,
+                &texture_bind_group_layout,
+                &camera_bindings.camera_bind_group_layout,
+                &light_bindings.light_bind_group_layout,
+                &skin_bind_group_layout,
+            );
+        let pipelines = Renderer::create_block_pipelines(
+            &device,
+            color_format,
+            &block_pipeline_layout,
+            &skinned_pipeline_layout,
+            include_str!("shader.wgsl"),
+        );
+        let light_render_pipeline = Renderer::create_light_pipeline(
+            &device,
+            color_format,
+            &light_pipeline_layout,
+            include_str!("light.wgsl"),
+        );
+        let particle_render_pipeline = Renderer::create_particle_pipeline(
+            &device,
+            color_format,
+            &block_pipeline_layout,
+            include_str!("particle.wgsl"),
+        );
+
+        Self {
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            color_format,
+            width,
+            height,
+            // Camera configuration
+            camera_uniform,
+            camera_projection,
+            camera_buffer: camera_bindings.camera_buffer,
+            lod_settings,
+            // Lighting
+            light_uniform,
+            light_buffer: light_bindings.light_buffer,
+            shadow_map,
+            sky,
+            outline,
+            overlay,
+            // Rendering
+            block_pipeline_layout,
+            skinned_pipeline_layout,
+            light_pipeline_layout,
+            render_pipeline: pipelines.render_pipeline,
+            cutout_render_pipeline: pipelines.cutout_render_pipeline,
+            translucent_render_pipeline: pipelines.translucent_render_pipeline,
+            skinned_render_pipeline: pipelines.skinned_render_pipeline,
+            light_render_pipeline,
+            particle_render_pipeline,
+            depth_map,
+            // Bind groups
+            camera_bind_group: camera_bindings.camera_bind_group,
+            light_bind_group: light_bindings.light_bind_group,
+            texture_bind_group_layout: Arc::new(texture_bind_group_layout),
+            skin_bind_group_layout,
+        }
+    }
+
+    fn create_texture_bindings(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &bind_group_layouts::TEXTURE_ENTRIES,
+            label: Some("texture_bind_group_layout"),
+        })
+    }
+
+    /// Joint matrices of one skinned entity, picked out of a larger buffer by the offset.
+    fn create_skin_bindings(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &bind_group_layouts::SKIN_ENTRIES,
+            label: Some("skin_bind_group_layout"),
+        })
+    }
+
+    fn create_camera_bindings(
+        device: &wgpu::Device,
+        camera_uniform: CameraUniform,
+    ) -> CameraBindings {
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &bind_group_layouts::CAMERA_ENTRIES,
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        CameraBindings {
+            camera_bind_group_layout,
+            camera_bind_group,
+            camera_buffer,
+        }
+    }
+
+    fn create_light_bindings(
+        device: &wgpu::Device,
+        light_uniform: crate::LightUniform,
+        shadow_map: &crate::shadow::ShadowMap,
+        sky: &crate::sky::Sky,
+    ) -> LightBindings {
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::cast_slice(&[light_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let light_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &bind_group_layouts::LIGHT_ENTRIES,
+                label: Some("light_bind_group_layout"),
+            });
+
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &light_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: shadow_map.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: sky.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("light_bind_group"),
+        });
+
+        LightBindings {
+            light_bind_group_layout,
+            light_bind_group,
+            light_buffer,
+        }
+    }
+
+    /// Pipeline layouts for the block, skinned and light pipelines, which are kept so the
+    /// pipelines can be rebuilt when their shaders change.
+    fn complete_bindings(
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        skin_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> (
+        wgpu::PipelineLayout,
+        wgpu::PipelineLayout,
+        wgpu::PipelineLayout,
+    ) {
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    texture_bind_group_layout,
+                    camera_bind_group_layout,
+                    light_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let skinned_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skinned Pipeline Layout"),
+                bind_group_layouts: &[
+                    texture_bind_group_layout,
+                    camera_bind_group_layout,
+                    light_bind_group_layout,
+                    skin_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, light_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        (
+            render_pipeline_layout,
+            skinned_pipeline_layout,
+            light_pipeline_layout,
+        )
+    }
+
+    fn create_block_pipelines(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        layout: &wgpu::PipelineLayout,
+        skinned_layout: &wgpu::PipelineLayout,
+        source: &str,
+    ) -> BlockPipelines {
+        let shader = wgpu::ShaderModuleDescriptor {
+            label: Some("Normal Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        };
+        let block_pipeline = |options: PipelineOptions| {
+            Renderer::create_render_pipeline(
+                device,
+                color_format,
+                layout,
+                Some(crate::texture::Texture::DEPTH_FORMAT),
+                &[
+                    crate::model::ModelVertex::desc(),
+                    crate::InstanceRaw::desc(),
+                ],
+                shader.clone(),
+                options,
+            )
+        };
+        let render_pipeline = block_pipeline(PipelineOptions::default());
+        // Leaves and similar blocks discard transparent pixels and can be seen from both sides
+        let cutout_render_pipeline = block_pipeline(PipelineOptions {
+            fragment_entry_point: "fs_cutout",
+            cull_mode: None,
+            ..Default::default()
+        });
+        // Glass and water blend over what is behind them and must not hide each other
+        let translucent_render_pipeline = block_pipeline(PipelineOptions {
+            blend: wgpu::BlendState::ALPHA_BLENDING,
+            depth_write_enabled: false,
+            cull_mode: None,
+            ..Default::default()
+        });
+        // Animated models bend their meshes along the joints of their skeleton
+        let skinned_render_pipeline = Renderer::create_render_pipeline(
+            device,
+            color_format,
+            skinned_layout,
+            Some(crate::texture::Texture::DEPTH_FORMAT),
+            &[
+                crate::model::ModelVertex::desc(),
+                crate::InstanceRaw::desc(),
+                crate::model::SkinVertex::desc(),
+            ],
+            shader.clone(),
+            PipelineOptions {
+                vertex_entry_point: "vs_skinned",
+                ..Default::default()
+            },
+        );
+
+        BlockPipelines {
+            render_pipeline,
+            cutout_render_pipeline,
+            translucent_render_pipeline,
+            skinned_render_pipeline,
+        }
+    }
+
+    /// Draws a small cube at the light's position so it can be seen in the scene
+    fn create_light_pipeline(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        layout: &wgpu::PipelineLayout,
+        source: &str,
+    ) -> wgpu::RenderPipeline {
+        let shader = wgpu::ShaderModuleDescriptor {
+            label: Some("Light Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        };
+        Renderer::create_render_pipeline(
+            device,
+            color_format,
+            layout,
+            Some(crate::texture::Texture::DEPTH_FORMAT),
+            &[crate::model::ModelVertex::desc()],
+            shader,
+            PipelineOptions::default(),
+        )
+    }
+
+    /// Particles share the bind groups of the blocks but build their quads from the vertex
+    /// index and one instance per particle.
+    fn create_particle_pipeline(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        layout: &wgpu::PipelineLayout,
+        source: &str,
+    ) -> wgpu::RenderPipeline {
+        let shader = wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        };
+        Renderer::create_render_pipeline(
+            device,
+            color_format,
+            layout,
+            Some(crate::texture::Texture::DEPTH_FORMAT),
+            &[crate::ParticleRaw::desc()],
+            shader,
+            // Blended like translucent blocks and seen from both sides
+            PipelineOptions {
+                blend: wgpu::BlendState::ALPHA_BLENDING,
+                depth_write_enabled: false,
+                cull_mode: None,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Rebuilds the pipelines that use `shader` from `source`. The source is validated
+    /// first and the current pipelines stay in use if anything goes wrong.
+    pub fn reload_shader(&mut self, shader: Shader, source: &str) -> anyhow::Result<()> {
+        shaders::validate(shader, source)?;
+        let device = &self.device;
+        match shader {
+            Shader::Block => {
+                let pipelines = shaders::try_create(device, || {
+                    Renderer::create_block_pipelines(
+                        device,
+                        self.color_format,
+                        &self.block_pipeline_layout,
+                        &self.skinned_pipeline_layout,
+                        source,
+                    )
+                })?;
+                self.render_pipeline = pipelines.render_pipeline;
+                self.cutout_render_pipeline = pipelines.cutout_render_pipeline;
+                self.translucent_render_pipeline = pipelines.translucent_render_pipeline;
+                self.skinned_render_pipeline = pipelines.skinned_render_pipeline;
+            }
+            Shader::Light => {
+                self.light_render_pipeline = shaders::try_create(device, || {
+                    Renderer::create_light_pipeline(
+                        device,
+                        self.color_format,
+                        &self.light_pipeline_layout,
+                        source,
+                    )
+                })?;
+            }
+            Shader::Shadow => self.shadow_map.reload_shader(device, source)?,
+            Shader::Sky => self.sky.reload_shader(device, source)?,
+            Shader::Outline => self.outline.reload_shader(device, source)?,
+            Shader::Overlay => self.overlay.reload_shader(device, source)?,
+            Shader::Particle => {
+                self.particle_render_pipeline = shaders::try_create(device, || {
+                    Renderer::create_particle_pipeline(
+                        device,
+                        self.color_format,
+                        &self.block_pipeline_layout,
+                        source,
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        layout: &wgpu::PipelineLayout,
+        depth_format: Option<wgpu::TextureFormat>,
+        vertex_layouts: &[wgpu::VertexBufferLayout],
+        shader: wgpu::ShaderModuleDescriptor,
+        options: PipelineOptions,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(shader);
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{:?}", shader)),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: options.vertex_entry_point,
+                buffers: vertex_layouts,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: options.fragment_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(options.blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: options.cull_mode,
+                polygon_mode: wgpu::PolygonMode::Fill, // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                unclipped_depth: false,                // Requires Features::DEPTH_CLIP_CONTROL
+                conservative: false, // Requires Features::CONSERVATIVE_RASTERIZATION
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: options.depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // If the pipeline will be used with a multiview render pass, this
+            // indicates how many array layers the attachments will have.
+            multiview: None,
+        })
+    }
+
+    /// A renderer without a window, drawing into an [`crate::offscreen::OffscreenTarget`].
+    /// Prefers the software fallback adapter so it also works on machines without a GPU.
+    pub async fn new_headless(width: u32, height: u32) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            dx12_shader_compiler: Default::default(),
+        });
+        let mut adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await;
+        if adapter.is_none() {
+            // Not every backend ships a fallback adapter, any adapter will do then
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: false,
+                })
+                .await;
+        }
+        let adapter = adapter.ok_or_else(|| anyhow::anyhow!("No wgpu adapter available"))?;
+        let (device, queue) = request_device(&adapter).await?;
+
+        Ok(Self::new(
+            device,
+            queue,
+            crate::offscreen::OffscreenTarget::FORMAT,
+            width,
+            height,
+        ))
+    }
+
+    /// Draws `world` into `view`, which has to be `color_format` and sized like the renderer.
+    pub fn render(&self, world: &crate::world::World, view: &wgpu::TextureView) -> RenderStats {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        // One instance buffer holding the opaque, cutout and translucent blocks one after another
+        let camera_position = self.camera_uniform.view_position();
+        let mut chunks = [0; LOD_LEVELS + 1];
+        let mut occluded = 0;
+        let mut opaque = Vec::new();
+        let mut cutout = Vec::new();
+        let mut translucent = Vec::new();
+        let mut add = |kind: BlockKind, instance| match kind.layer() {
+            BlockLayer::Opaque => opaque.push(instance),
+            BlockLayer::Cutout => cutout.push(instance),
+            BlockLayer::Translucent => translucent.push(instance),
+        };
+        // Sections walled off from the camera, e.g. caves seen from the surface, are skipped
+        let radius = (self.lod_settings.render_distance / CHUNK_SIZE as f32).ceil() as i32 + 1;
+        let visible_sections = world.visible_sections(camera_position, radius);
+        let is_visible = |section: Vector3<i32>| {
+            visible_sections
+                .as_ref()
+                .is_none_or(|visible| visible.contains(&section))
+        };
+        // Nearby chunks are drawn block by block, farther ones with fewer but larger boxes
+        for (coords, chunk) in world.chunks() {
+            let distance = crate::lod::chunk_distance(*coords, camera_position);
+            let Some(level) = self.lod_settings.level(distance) else {
+                continue;
+            };
+            chunks[level] += 1;
+            if level == 0 {
+                for coords in chunk.blocks() {
+                    if !is_visible(section_of(*coords)) {
+                        occluded += 1;
+                        continue;
+                    }
+                    let block = &world.blocks()[coords];
+                    add(block.kind, block.to_instance(world.atlas()));
+                }
+            } else {
+                for lod_block in chunk.lod(level) {
+                    // Boxes can reach through several sections stacked on top of each other
+                    let bottom = (lod_block.min.y as i32 + 1).div_euclid(CHUNK_SIZE);
+                    let top = (lod_block.max.y as i32 - 1).div_euclid(CHUNK_SIZE);
+                    if !(bottom..=top).any(|y| is_visible(Vector3::new(coords.x, y, coords.y))) {
+                        occluded += 1;
+                        continue;
+                    }
+                    add(lod_block.kind, lod_block.to_instance(world.atlas()));
+                }
+            }
+        }
+        // Entities are left out where the blocks around them would be
+        let entity_batches = world.entities.batches(|entity| {
+            let coords = block_at(Point3::from_vec(entity.position));
+            let distance = crate::lod::chunk_distance(chunk_of(coords), camera_position);
+            self.lod_settings.level(distance).is_some() && is_visible(section_of(coords))
+        });
+        // Blending only looks right if the farthest blocks are drawn first
+        translucent.sort_by(|a, b| {
+            let distance_a = (Point3::from_vec(a.position) - camera_position).magnitude2();
+            let distance_b = (Point3::from_vec(b.position) - camera_position).magnitude2();
+            distance_b.total_cmp(&distance_a)
+        });
+        let opaque_range = 0..opaque.len() as u32;
+        let cutout_range = opaque_range.end..opaque_range.end + cutout.len() as u32;
+        let translucent_range = cutout_range.end..cutout_range.end + translucent.len() as u32;
+        // Followed by the instances of every entity model. Skinned models also note where
+        // the joint matrices of their first entity start in the skin buffer.
+        let mut entity_ranges = Vec::new();
+        let mut entity_instances = Vec::new();
+        let mut palettes: Vec<[[f32; 4]; 4]> = Vec::new();
+        let mut entity_end = translucent_range.end;
+        for (model, entities) in &entity_batches {
+            // Still loading in the background
+            let Some(model) = world.models.get(*model) else {
+                continue;
+            };
+            let skin = model
+                .skeleton
+                .as_ref()
+                .map(|_| (palettes.len() / MAX_JOINTS) as u32 * PALETTE_SIZE);
+            for entity in entities {
+                entity_instances.push(entity.to_instance());
+                if let Some(matrices) = world.models.joint_matrices(entity) {
+                    let start = palettes.len();
+                    palettes.extend(matrices.into_iter().map(Into::<[[f32; 4]; 4]>::into));
+                    palettes.resize(start + MAX_JOINTS, [[0.0; 4]; 4]);
+                }
+            }
+            entity_ranges.push((model, entity_end..entity_end + entities.len() as u32, skin));
+            entity_end += entities.len() as u32;
+        }
+
+        let instance_data = opaque
+            .into_iter()
+            .chain(cutout)
+            .chain(translucent)
+            .chain(entity_instances)
+            .map(|instance| instance.to_raw())
+            .collect::<Vec<_>>();
+        let instance_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let skin_buffer = (!palettes.is_empty()).then(|| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Skin Buffer"),
+                    contents: bytemuck::cast_slice(&palettes),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                })
+        });
+        let skin_bind_group = skin_buffer.as_ref().map(|buffer| {
+            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.skin_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(PALETTE_SIZE as u64),
+                    }),
+                }],
+                label: Some("skin_bind_group"),
+            })
+        });
+
+        // Particles are sorted back to front for the same reason
+        let mut particles: Vec<_> = world.particles.particles().iter().collect();
+        particles.sort_by(|a, b| {
+            let distance_a = (Point3::from_vec(a.position) - camera_position).magnitude2();
+            let distance_b = (Point3::from_vec(b.position) - camera_position).magnitude2();
+            distance_b.total_cmp(&distance_a)
+        });
+        let particle_data = particles
+            .into_iter()
+            .map(crate::ParticleRaw::from)
+            .collect::<Vec<_>>();
+        let particle_buffer = (!particle_data.is_empty()).then(|| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Particle Buffer"),
+                    contents: bytemuck::cast_slice(&particle_data),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+        });
+
+        let mut stats = RenderStats {
+            buffer_memory: self.buffer_memory()
+                + instance_buffer.size()
+                + skin_buffer.as_ref().map_or(0, |buffer| buffer.size())
+                + particle_buffer.as_ref().map_or(0, |buffer| buffer.size()),
+            chunks,
+            occluded,
+            entities: entity_end - translucent_range.end,
+            particles: particle_data.len() as u32,
+            ..Default::default()
+        };
+        for mesh in &world.block_model().meshes {
+            stats.buffer_memory += mesh.vertex_buffer.size() + mesh.index_buffer.size();
+        }
+
+        // Translucent blocks let light through and do not cast shadows. Neither do cutout
+        // blocks, the depth only shadow pass cannot discard their transparent texels and
+        // leaves would cast the shadow of a solid cube.
+        let mut shadow_casters = vec![(world.block_model(), opaque_range.clone(), None)];
+        shadow_casters.extend(entity_ranges.iter().cloned());
+        for _ in 0..crate::shadow::CASCADE_COUNT {
+            for (model, instances, _) in &shadow_casters {
+                stats.record_model(model, instances.len() as u32);
+            }
+        }
+        self.shadow_map.render(
+            &mut encoder,
+            &shadow_casters,
+            &instance_buffer,
+            skin_bind_group.as_ref(),
+            PALETTE_SIZE,
+        );
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.sky.clear_color()),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_map.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            self.sky.render(&mut render_pass);
+            stats.record_draw(1);
+
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+
+            render_pass.set_pipeline(&self.light_render_pipeline);
+            render_pass.draw_light_model(
+                world.block_model(),
+                &self.camera_bind_group,
+                &self.light_bind_group,
+            );
+            stats.record_model(world.block_model(), 1);
+
+            // Blocks share the atlas, entities use the materials of their own model.
+            // Entities are opaque and have to be in the depth buffer before anything is
+            // blended over them.
+            let blocks = (world.block_model(), Some(&world.atlas_material));
+            let mut draws = vec![
+                (&self.render_pipeline, blocks, opaque_range, None),
+                (&self.cutout_render_pipeline, blocks, cutout_range, None),
+            ];
+            draws.extend(entity_ranges.iter().map(|(model, instances, skin)| {
+                (
+                    &self.render_pipeline,
+                    (*model, None),
+                    instances.clone(),
+                    *skin,
+                )
+            }));
+            draws.push((
+                &self.translucent_render_pipeline,
+                blocks,
+                translucent_range,
+                None,
+            ));
+            for (pipeline, (model, material), instances, skin) in draws {
+                if instances.is_empty() {
+                    continue;
+                }
+                render_pass.set_pipeline(pipeline);
+                stats.record_model(model, instances.len() as u32);
+                match (material, skin.zip(skin_bind_group.as_ref())) {
+                    (_, Some((offset, skin_bind_group))) => self.draw_skinned(
+                        &mut render_pass,
+                        model,
+                        instances,
+                        offset,
+                        skin_bind_group,
+                    ),
+                    (Some(material), None) => render_pass.draw_model_instanced_with_material(
+                        model,
+                        material,
+                        instances,
+                        &self.camera_bind_group,
+                        &self.light_bind_group,
+                    ),
+                    (None, None) => render_pass.draw_model_instanced(
+                        model,
+                        instances,
+                        &self.camera_bind_group,
+                        &self.light_bind_group,
+                    ),
+                }
+            }
+
+            if let Some(particle_buffer) = &particle_buffer {
+                render_pass.set_pipeline(&self.particle_render_pipeline);
+                render_pass.set_bind_group(0, &world.atlas_material.bind_group, &[]);
+                render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, particle_buffer.slice(..));
+                render_pass.draw(0..6, 0..stats.particles);
+                stats.record_draw(stats.particles as u64 * 2);
+            }
+
+            self.outline
+                .render(&mut render_pass, &self.camera_bind_group);
+            if self.outline.is_visible() {
+                // The highlighted face and the lines
+                stats.record_draw(2);
+                stats.record_draw(0);
+            }
+        }
+        self.overlay.render(&self.device, &mut encoder, view);
+        for triangles in self.overlay.batch_triangles() {
+            stats.record_draw(triangles);
+        }
+        self.queue.submit(iter::once(encoder.finish()));
+        stats
+    }
+
+    /// Draws every instance of a skinned model with joint matrices of its own, which start
+    /// at `offset` in the buffer of `skin_bind_group`. Meshes without a skin are drawn for
+    /// all instances at once.
+    fn draw_skinned<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        model: &'a crate::model::Model,
+        instances: std::ops::Range<u32>,
+        offset: u32,
+        skin_bind_group: &'a wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            let Some(skin_buffer) = &mesh.skin_buffer else {
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.draw_mesh_instanced(
+                    mesh,
+                    material,
+                    instances.clone(),
+                    &self.camera_bind_group,
+                    &self.light_bind_group,
+                );
+                continue;
+            };
+            render_pass.set_pipeline(&self.skinned_render_pipeline);
+            render_pass.set_vertex_buffer(2, skin_buffer.slice(..));
+            for (i, instance) in instances.clone().enumerate() {
+                render_pass.set_bind_group(3, skin_bind_group, &[offset + i as u32 * PALETTE_SIZE]);
+                render_pass.draw_mesh_instanced(
+                    mesh,
+                    material,
+                    instance..instance + 1,
+                    &self.camera_bind_group,
+                    &self.light_bind_group,
+                );
+            }
+        }
+    }
+
+    /// Bytes in the buffers that live as long as the renderer, plus the overlay quads.
+    fn buffer_memory(&self) -> u64 {
+        self.camera_buffer.size()
+            + self.light_buffer.size()
+            + self.shadow_map.buffer_memory()
+            + self.sky.uniform_buffer.size()
+            + self.outline.buffer_memory()
+            + self.overlay.buffer_memory()
+    }
+
+    pub fn update(&mut self, position: &Point3<f32>, pitch: Rad<f32>, yaw: Rad<f32>) {
+        let view = view_matrix(*position, pitch, yaw);
+        // Shadows are cast by whichever of sun or moon is currently up
+        let (light_direction, light_color) = self.sky.time_of_day.light();
+        self.shadow_map.sun_direction = light_direction;
+        self.shadow_map.sun_color = light_color;
+        self.shadow_map
+            .update(&self.queue, view, &self.camera_projection);
+        self.sky.update(
+            &self.queue,
+            self.camera_projection.calc_matrix() * view,
+            &self.camera_projection,
+        );
+        self.camera_uniform
+            .update_view_projection(*position, pitch, yaw, &self.camera_projection);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+    }
+
+    pub fn lod_settings(&self) -> LodSettings {
+        self.lod_settings
+    }
+
+    /// Changes the render distance and level of detail. The far plane follows the render
+    /// distance from the next [`Renderer::update`] on.
+    pub fn set_lod_settings(&mut self, settings: LodSettings) {
+        self.lod_settings = settings;
+        self.camera_projection.set_zfar(settings.render_distance);
+    }
+
+    pub fn set_light(&mut self, position: Vector3<f32>, color: Vector3<f32>) {
+        self.light_uniform.position = position.into();
+        self.light_uniform.color = color.into();
+        self.queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_uniform]),
+        );
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.camera_projection.resize(width, height);
+        self.depth_map = crate::texture::Texture::create_depth_texture(
+            &self.device,
+            width,
+            height,
+            "depth_texture",
+        );
+    }
+}
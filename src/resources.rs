@@ -1,24 +1,222 @@
-use std::io::{BufReader, Cursor};
+use anyhow::Context;
+use base64::Engine;
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
+use std::{
+    collections::HashMap,
+    io::{BufReader, Cursor},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 use wgpu::util::DeviceExt;
 
-use crate::{model, texture};
+use crate::{
+    assets::TextureCache,
+    model,
+    skeleton::{AnimationClip, Channel, Interpolation, Joint, Keyframes, Skeleton, Transform},
+    texture,
+};
 
-pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let txt = std::fs::read_to_string(path)?;
+/// Set to the directory assets are loaded from, by default `res` next to the executable
+pub const ASSET_ROOT_ENV: &str = "ASSET_ROOT";
+/// Set to resource pack directories separated like `PATH`, the first one wins
+pub const RESOURCE_PACKS_ENV: &str = "RESOURCE_PACKS";
+
+// Every file of res/ at build time, by its path below res/
+static EMBEDDED: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded_res.rs"));
+
+static SOURCES: RwLock<Option<AssetSources>> = RwLock::new(None);
 
-    Ok(txt)
+/// Directories assets are looked up in. A file is taken from the first resource pack
+/// that has it, then from the asset root, and the copy built into the binary comes last.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssetSources {
+    pub root: Option<PathBuf>,
+    // Highest priority first
+    pub packs: Vec<PathBuf>,
 }
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let data = std::fs::read(path)?;
+impl AssetSources {
+    /// Reads [`ASSET_ROOT_ENV`] and [`RESOURCE_PACKS_ENV`]. Without an asset root the `res`
+    /// directory next to the executable is used if there is one.
+    pub fn from_env() -> Self {
+        let root = std::env::var_os(ASSET_ROOT_ENV)
+            .map(PathBuf::from)
+            .or_else(|| {
+                let dir = std::env::current_exe().ok()?.parent()?.join("res");
+                dir.is_dir().then_some(dir)
+            });
+        let packs = std::env::var_os(RESOURCE_PACKS_ENV)
+            .map(|packs| std::env::split_paths(&packs).collect())
+            .unwrap_or_default();
+        Self { root, packs }
+    }
+
+    /// The file on disk `file_name` is loaded from, `None` if only the built in copy has it.
+    pub fn find(&self, file_name: &str) -> Option<PathBuf> {
+        self.packs
+            .iter()
+            .chain(&self.root)
+            .map(|dir| dir.join(file_name))
+            .find(|path| path.is_file())
+    }
+
+    /// Whether `file_name` is on disk or built in.
+    pub fn contains(&self, file_name: &str) -> bool {
+        self.find(file_name).is_some() || embedded(file_name).is_some()
+    }
+
+    /// Name of every file in the packs, the asset root and the binary, sorted and without
+    /// the copies a higher priority source hides.
+    pub fn file_names(&self) -> anyhow::Result<Vec<String>> {
+        let mut names: Vec<String> = EMBEDDED.iter().map(|(name, _)| name.to_string()).collect();
+        for dir in self.packs.iter().chain(&self.root) {
+            list_files(dir, "", &mut names)?;
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    pub fn read(&self, file_name: &str) -> anyhow::Result<Vec<u8>> {
+        match self.find(file_name) {
+            Some(path) => {
+                std::fs::read(&path).with_context(|| format!("Could not read {}", path.display()))
+            }
+            None => embedded(file_name)
+                .map(<[u8]>::to_vec)
+                .with_context(|| format!("There is no asset called {}", file_name)),
+        }
+    }
+}
+
+/// Adds the files below `dir` to `names` as paths relative to it, separated by `/`.
+fn list_files(dir: &Path, prefix: &str, names: &mut Vec<String>) -> anyhow::Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Could not list {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), names)?;
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// The copy of `file_name` built into the binary.
+pub fn embedded(file_name: &str) -> Option<&'static [u8]> {
+    EMBEDDED
+        .iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, data)| *data)
+}
+
+/// Where assets are loaded from, set up with [`AssetSources::from_env`] unless
+/// [`set_asset_sources`] was called first.
+pub fn asset_sources() -> AssetSources {
+    if let Some(sources) = SOURCES.read().unwrap().as_ref() {
+        return sources.clone();
+    }
+    SOURCES
+        .write()
+        .unwrap()
+        .get_or_insert_with(AssetSources::from_env)
+        .clone()
+}
+
+/// Changes where assets are loaded from, files that were already loaded stay as they are.
+pub fn set_asset_sources(sources: AssetSources) {
+    *SOURCES.write().unwrap() = Some(sources);
+}
+
+/// Why a model could not be loaded.
+#[derive(Debug)]
+pub enum ModelError {
+    // The model itself or a file it refers to is in none of the asset sources
+    MissingFile { model: String, file: String },
+    // Only meshes with textures need texture coordinates
+    MissingTexCoords { model: String, mesh: String },
+    MissingNormals { model: String, mesh: String },
+    // A mesh uses a material no material library of the model defines
+    BadMaterial { model: String, material: String },
+    Unsupported { model: String, feature: String },
+    // Anything else, like syntax errors or images that do not decode
+    Invalid { model: String, error: anyhow::Error },
+}
+
+impl ModelError {
+    /// The file that failed to load.
+    pub fn model(&self) -> &str {
+        match self {
+            ModelError::MissingFile { model, .. }
+            | ModelError::MissingTexCoords { model, .. }
+            | ModelError::MissingNormals { model, .. }
+            | ModelError::BadMaterial { model, .. }
+            | ModelError::Unsupported { model, .. }
+            | ModelError::Invalid { model, .. } => model,
+        }
+    }
+}
+
+impl std::fmt::Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::MissingFile { model, file } if model == file => {
+                write!(f, "There is no asset called {}", file)
+            }
+            ModelError::MissingFile { model, file } => {
+                write!(f, "{} refers to {}, which does not exist", model, file)
+            }
+            ModelError::MissingTexCoords { model, mesh } => write!(
+                f,
+                "Mesh {} of {} has a texture but no texture coordinates",
+                mesh, model
+            ),
+            ModelError::MissingNormals { model, mesh } => {
+                write!(f, "Mesh {} of {} has no normals", mesh, model)
+            }
+            ModelError::BadMaterial { model, material } => write!(
+                f,
+                "{} uses the material {}, which none of its material libraries defines",
+                model, material
+            ),
+            ModelError::Unsupported { model, feature } => {
+                write!(f, "{} uses {}, which is not supported", model, feature)
+            }
+            ModelError::Invalid { model, error } => write!(f, "{} is invalid: {:#}", model, error),
+        }
+    }
+}
 
-    Ok(data)
+impl std::error::Error for ModelError {}
+
+/// Fails with [`ModelError::MissingFile`] unless there is an asset called `file`.
+fn require(model: &str, file: &str) -> Result<(), ModelError> {
+    if asset_sources().contains(file) {
+        return Ok(());
+    }
+    Err(ModelError::MissingFile {
+        model: model.to_string(),
+        file: file.to_string(),
+    })
+}
+
+fn unsupported(model: &str, feature: impl Into<String>) -> ModelError {
+    ModelError::Unsupported {
+        model: model.to_string(),
+        feature: feature.into(),
+    }
+}
+
+pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
+    let data = load_binary(file_name).await?;
+    String::from_utf8(data).with_context(|| format!("{} is not UTF-8 text", file_name))
+}
+
+pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
+    asset_sources().read(file_name)
 }
 
 pub async fn load_texture(
@@ -31,16 +229,62 @@ pub async fn load_texture(
     texture::Texture::from_bytes(device, queue, &data, file_name, is_normal_map)
 }
 
+/// Loads a Wavefront OBJ model, or a glTF one if the file ends in `.gltf` or `.glb`.
+/// Texture files are shared through `textures` with the models loaded before.
 pub async fn load_model(
     file_name: &str,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     layout: &wgpu::BindGroupLayout,
+    textures: &TextureCache,
+) -> Result<model::Model, ModelError> {
+    let model = match Path::new(file_name).extension().and_then(|e| e.to_str()) {
+        Some("gltf" | "glb") => load_gltf(file_name, device, queue, layout).await,
+        Some("obj") => load_obj(file_name, device, queue, layout, textures).await,
+        _ => {
+            return Err(unsupported(
+                file_name,
+                "a file type other than OBJ and glTF",
+            ))
+        }
+    };
+    // The loaders return a ModelError where they know what is wrong
+    model.map_err(|error| match error.downcast::<ModelError>() {
+        Ok(error) => error,
+        Err(error) => ModelError::Invalid {
+            model: file_name.to_string(),
+            error,
+        },
+    })
+}
+
+async fn load_obj(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    textures: &TextureCache,
 ) -> anyhow::Result<model::Model> {
+    require(file_name, file_name)?;
     let obj_text = load_string(file_name).await?;
+
+    // tobj would only log missing material libraries and names it does not know
+    let mut used_materials = Vec::new();
+    for line in obj_text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("mtllib") => {
+                if let Some(library) = words.next() {
+                    require(file_name, library)?;
+                }
+            }
+            Some("usemtl") => used_materials.push(words.collect::<Vec<_>>().join(" ")),
+            _ => {}
+        }
+    }
+
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
-
     let (models, obj_materials) = tobj::load_obj_buf_async(
         &mut obj_reader,
         &tobj::LoadOptions {
@@ -49,38 +293,424 @@ pub async fn load_model(
             ..Default::default()
         },
         |p| async move {
-            println!("failed {}", &p);
-            let mat_text = load_string(&p).await.unwrap();
+            let mat_text = load_string(&p)
+                .await
+                .map_err(|_| tobj::LoadError::OpenFileFailed)?;
             tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
         },
     )
     .await?;
+    let obj_materials = obj_materials
+        .with_context(|| format!("Could not load the material libraries of {}", file_name))?;
+    if let Some(material) = used_materials
+        .into_iter()
+        .find(|name| obj_materials.iter().all(|m| m.name != *name))
+    {
+        return Err(ModelError::BadMaterial {
+            model: file_name.to_string(),
+            material,
+        }
+        .into());
+    }
 
     let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, false, device, queue).await?;
+    for m in &obj_materials {
+        let diffuse_texture = if m.diffuse_texture.is_empty() {
+            let [r, g, b] = m.diffuse;
+            Arc::new(solid_texture([r, g, b, 1.0], &m.name, device, queue)?)
+        } else {
+            require(file_name, &m.diffuse_texture)?;
+            textures
+                .load(&m.diffuse_texture, false, device, queue)
+                .await?
+        };
+        let normal_texture = if m.normal_texture.is_empty() {
+            Arc::new(texture::Texture::flat_normal_map(device, queue)?)
+        } else {
+            require(file_name, &m.normal_texture)?;
+            textures
+                .load(&m.normal_texture, true, device, queue)
+                .await?
+        };
 
         materials.push(model::Material::new(
             device,
             &m.name,
             diffuse_texture,
+            normal_texture,
             layout,
         ));
     }
 
-    let meshes = models
-        .into_iter()
-        .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
-                .map(|i| model::ModelVertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
+    let mut meshes = Vec::new();
+    let mut needs_default_material = false;
+    for m in models {
+        let vertex_count = m.mesh.positions.len() / 3;
+        let textured = m.mesh.material_id.is_some_and(|id| {
+            !obj_materials[id].diffuse_texture.is_empty()
+                || !obj_materials[id].normal_texture.is_empty()
+        });
+        let has_tex_coords = m.mesh.texcoords.len() == vertex_count * 2;
+        if textured && !has_tex_coords {
+            return Err(ModelError::MissingTexCoords {
+                model: file_name.to_string(),
+                mesh: m.name,
+            }
+            .into());
+        }
+        if m.mesh.normals.len() != vertex_count * 3 {
+            return Err(ModelError::MissingNormals {
+                model: file_name.to_string(),
+                mesh: m.name,
+            }
+            .into());
+        }
+
+        let mut vertices = (0..vertex_count)
+            .map(|i| model::ModelVertex {
+                position: [
+                    m.mesh.positions[i * 3],
+                    m.mesh.positions[i * 3 + 1],
+                    m.mesh.positions[i * 3 + 2],
+                ],
+                tex_coords: if has_tex_coords {
+                    [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]]
+                } else {
+                    [0.0; 2]
+                },
+                normal: [
+                    m.mesh.normals[i * 3],
+                    m.mesh.normals[i * 3 + 1],
+                    m.mesh.normals[i * 3 + 2],
+                ],
+                // Filled in by calculate_tangents
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
+            })
+            .collect::<Vec<_>>();
+        calculate_tangents(&mut vertices, &m.mesh.indices);
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Vertex Buffer", file_name)),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Index Buffer", file_name)),
+            contents: bytemuck::cast_slice(&m.mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        // Meshes without a material get a plain white one after all others
+        let material = m.mesh.material_id.unwrap_or_else(|| {
+            needs_default_material = true;
+            materials.len()
+        });
+        meshes.push(model::Mesh {
+            name: file_name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: m.mesh.indices.len() as u32,
+            material,
+            skin_buffer: None,
+        });
+    }
+    if needs_default_material {
+        materials.push(default_material(file_name, device, queue, layout)?);
+    }
+
+    Ok(model::Model {
+        meshes,
+        materials,
+        skeleton: None,
+        animations: Vec::new(),
+    })
+}
+
+/// Loads a glTF 2.0 model, either a `.glb` or a `.gltf` whose buffers and images are
+/// files next to it or data URIs. Meshes without a skin are moved into place along the
+/// node hierarchy. Meshes bound to the skin of the file keep their own space and follow
+/// its skeleton, which is what the animations of the file move. Files with more than one
+/// skin, morph targets or required extensions are not supported.
+async fn load_gltf(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<model::Model> {
+    require(file_name, file_name)?;
+    let data = load_binary(file_name).await?;
+    let gltf = gltf::Gltf::from_slice(&data)
+        .with_context(|| format!("{} is not a valid glTF file", file_name))?;
+    if let Some(extension) = gltf.extensions_required().next() {
+        return Err(unsupported(file_name, format!("the {} extension", extension)).into());
+    }
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .with_context(|| format!("{} has no binary chunk", file_name))?,
+            gltf::buffer::Source::Uri(uri) => load_uri(file_name, uri).await?,
+        };
+        anyhow::ensure!(
+            data.len() >= buffer.length(),
+            "Buffer {} of {} is shorter than declared",
+            buffer.index(),
+            file_name
+        );
+        buffers.push(data);
+    }
+    let buffer_data = |buffer: gltf::Buffer| Some(&buffers[buffer.index()][..]);
+
+    let mut materials = Vec::new();
+    for material in gltf.materials() {
+        let name = material.name().unwrap_or(file_name);
+        let pbr = material.pbr_metallic_roughness();
+        let diffuse_texture = match pbr.base_color_texture() {
+            Some(info) => {
+                let image = load_gltf_image(file_name, info.texture().source(), &buffers).await?;
+                texture::Texture::from_bytes(device, queue, &image, name, false)?
+            }
+            None => solid_texture(pbr.base_color_factor(), name, device, queue)?,
+        };
+        let normal_texture = match material.normal_texture() {
+            Some(normal) => {
+                let image = load_gltf_image(file_name, normal.texture().source(), &buffers).await?;
+                texture::Texture::from_bytes(device, queue, &image, name, true)?
+            }
+            None => texture::Texture::flat_normal_map(device, queue)?,
+        };
+        materials.push(model::Material::new(
+            device,
+            name,
+            diffuse_texture,
+            normal_texture,
+            layout,
+        ));
+    }
+
+    // World transform of every node in the scene, parents come before their children
+    let mut parents = vec![None; gltf.nodes().len()];
+    for node in gltf.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .with_context(|| format!("{} has no scene", file_name))?;
+    let mut nodes = Vec::new();
+    let mut stack: Vec<_> = scene
+        .nodes()
+        .map(|node| (node, Matrix4::identity()))
+        .collect();
+    while let Some((node, parent)) = stack.pop() {
+        let global = parent * Matrix4::from(node.transform().matrix());
+        stack.extend(node.children().map(|child| (child, global)));
+        nodes.push((node, global));
+    }
+    let mut globals = vec![Matrix4::identity(); parents.len()];
+    for (node, global) in &nodes {
+        globals[node.index()] = *global;
+    }
+
+    let skin = gltf.skins().next();
+    let skeleton = match &skin {
+        Some(skin) => Some(load_skeleton(skin, &parents, &globals, buffer_data)?),
+        None => None,
+    };
+    let animations = match &skin {
+        Some(skin) => load_animations(&gltf, skin, buffer_data)?,
+        None => Vec::new(),
+    };
+
+    let mut meshes = Vec::new();
+    let mut needs_default_material = false;
+    for (node, global) in &nodes {
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        let skinned = node.skin().is_some();
+        if node.skin().is_some_and(|skin| skin.index() > 0) {
+            return Err(unsupported(file_name, "more than one skin").into());
+        }
+        let mesh_name = mesh
+            .name()
+            .map_or_else(|| mesh.index().to_string(), str::to_string);
+        let normal_matrix = Matrix3::from_cols(
+            global.x.truncate(),
+            global.y.truncate(),
+            global.z.truncate(),
+        )
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or_else(Matrix3::identity);
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                let feature = format!("{:?} instead of triangles", primitive.mode());
+                return Err(unsupported(file_name, feature).into());
+            }
+            if primitive.morph_targets().next().is_some() {
+                return Err(unsupported(file_name, "morph targets").into());
+            }
+            let material = primitive.material();
+            let textured = material
+                .pbr_metallic_roughness()
+                .base_color_texture()
+                .is_some()
+                || material.normal_texture().is_some();
+            let reader = primitive.reader(buffer_data);
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .with_context(|| {
+                    format!("Mesh {} of {} has no positions", mesh.index(), file_name)
+                })?
+                .collect();
+            let normals: Vec<[f32; 3]> = match reader.read_normals() {
+                Some(normals) => normals.collect(),
+                None => {
+                    return Err(ModelError::MissingNormals {
+                        model: file_name.to_string(),
+                        mesh: mesh_name,
+                    }
+                    .into())
+                }
+            };
+            let tex_coords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                Some(tex_coords) => tex_coords.into_f32().collect(),
+                // calculate_tangents gives these a basis around the normal instead
+                None if !textured => vec![[0.0; 2]; positions.len()],
+                None => {
+                    return Err(ModelError::MissingTexCoords {
+                        model: file_name.to_string(),
+                        mesh: mesh_name,
+                    }
+                    .into())
+                }
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            anyhow::ensure!(
+                normals.len() == positions.len() && tex_coords.len() == positions.len(),
+                "Mesh {} of {} has attributes of different lengths",
+                mesh.index(),
+                file_name
+            );
+            anyhow::ensure!(
+                indices.len().is_multiple_of(3),
+                "Mesh {} of {} has {} indices, which is not a whole number of triangles",
+                mesh.index(),
+                file_name,
+                indices.len()
+            );
+            if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+                anyhow::bail!(
+                    "Mesh {} of {} refers to vertex {}, but only has {}",
+                    mesh.index(),
+                    file_name,
+                    index,
+                    positions.len()
+                );
+            }
+
+            let mut vertices = positions
+                .iter()
+                .zip(&normals)
+                .zip(&tex_coords)
+                .map(|((&position, &normal), &tex_coords)| {
+                    // The skeleton places skinned meshes, all others are baked in place
+                    let (position, normal) = if skinned {
+                        (position, normal)
+                    } else {
+                        (
+                            (global * Vector3::from(position).extend(1.0))
+                                .truncate()
+                                .into(),
+                            (normal_matrix * Vector3::from(normal)).into(),
+                        )
+                    };
+                    model::ModelVertex {
+                        position,
+                        tex_coords,
+                        normal,
+                        // Filled in by calculate_tangents
+                        tangent: [0.0; 3],
+                        bitangent: [0.0; 3],
+                    }
                 })
                 .collect::<Vec<_>>();
+            calculate_tangents(&mut vertices, &indices);
+
+            let skin_buffer = if skinned {
+                let joints: Vec<[u16; 4]> = reader
+                    .read_joints(0)
+                    .with_context(|| {
+                        format!(
+                            "Skinned mesh {} of {} has no joints",
+                            mesh.index(),
+                            file_name
+                        )
+                    })?
+                    .into_u16()
+                    .collect();
+                let weights: Vec<[f32; 4]> = reader
+                    .read_weights(0)
+                    .with_context(|| {
+                        format!(
+                            "Skinned mesh {} of {} has no weights",
+                            mesh.index(),
+                            file_name
+                        )
+                    })?
+                    .into_f32()
+                    .collect();
+                anyhow::ensure!(
+                    joints.len() == positions.len() && weights.len() == positions.len(),
+                    "Skinned mesh {} of {} has joints or weights missing",
+                    mesh.index(),
+                    file_name
+                );
+                let joint_count = skeleton
+                    .as_ref()
+                    .map_or(0, |skeleton| skeleton.joints.len());
+                if let Some(joint) = joints
+                    .iter()
+                    .flatten()
+                    .find(|&&j| j as usize >= joint_count)
+                {
+                    anyhow::bail!(
+                        "Skinned mesh {} of {} refers to joint {}, but the skin only has {}",
+                        mesh.index(),
+                        file_name,
+                        joint,
+                        joint_count
+                    );
+                }
+                let skin_vertices = joints
+                    .iter()
+                    .zip(&weights)
+                    .map(|(joints, &weights)| model::SkinVertex {
+                        joints: joints.map(u32::from),
+                        weights,
+                    })
+                    .collect::<Vec<_>>();
+                Some(
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&format!("{:?} Skin Buffer", file_name)),
+                        contents: bytemuck::cast_slice(&skin_vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                )
+            } else {
+                None
+            };
 
             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{:?} Vertex Buffer", file_name)),
@@ -89,19 +719,334 @@ pub async fn load_model(
             });
             let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
+                contents: bytemuck::cast_slice(&indices),
                 usage: wgpu::BufferUsages::INDEX,
             });
 
-            model::Mesh {
+            // Primitives without a material get a plain white one after all others
+            let material = material.index().unwrap_or_else(|| {
+                needs_default_material = true;
+                materials.len()
+            });
+            meshes.push(model::Mesh {
                 name: file_name.to_string(),
                 vertex_buffer,
                 index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+                num_elements: indices.len() as u32,
+                material,
+                skin_buffer,
+            });
+        }
+    }
+    if needs_default_material {
+        materials.push(default_material(file_name, device, queue, layout)?);
+    }
+
+    Ok(model::Model {
+        meshes,
+        materials,
+        skeleton,
+        animations,
+    })
+}
+
+/// Contents of a buffer or image URI, either decoded from a data URI or read from a file
+/// relative to `file_name`.
+async fn load_uri(file_name: &str, uri: &str) -> anyhow::Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .with_context(|| format!("{} only supports base64 data URIs", file_name))?;
+        return Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?);
+    }
+    let path = match file_name.rsplit_once('/') {
+        Some((dir, _)) => format!("{}/{}", dir, uri),
+        None => uri.to_string(),
+    };
+    require(file_name, &path)?;
+    load_binary(&path).await
+}
+
+/// Encoded bytes of an image, embedded in a buffer or in a file of its own.
+async fn load_gltf_image(
+    file_name: &str,
+    image: gltf::Image<'_>,
+    buffers: &[Vec<u8>],
+) -> anyhow::Result<Vec<u8>> {
+    match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let data = &buffers[view.buffer().index()];
+            data.get(view.offset()..view.offset() + view.length())
+                .map(<[u8]>::to_vec)
+                .with_context(|| {
+                    format!("Image {} of {} is out of bounds", image.index(), file_name)
+                })
+        }
+        gltf::image::Source::Uri { uri, .. } => load_uri(file_name, uri).await,
+    }
+}
+
+/// Plain white, for meshes that do not name a material.
+fn default_material(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<model::Material> {
+    Ok(model::Material::new(
+        device,
+        file_name,
+        solid_texture([1.0; 4], file_name, device, queue)?,
+        texture::Texture::flat_normal_map(device, queue)?,
+        layout,
+    ))
+}
+
+/// A 1x1 texture for materials that only have a colour.
+fn solid_texture(
+    linear_color: [f32; 4],
+    label: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> anyhow::Result<texture::Texture> {
+    // Colours in glTF are linear but diffuse textures are sRGB
+    let [r, g, b, a] = linear_color;
+    let color = [
+        texture::linear_to_srgb(r),
+        texture::linear_to_srgb(g),
+        texture::linear_to_srgb(b),
+        a,
+    ]
+    .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    let img =
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+    texture::Texture::from_image(device, queue, &img, Some(label), false)
+}
+
+fn load_skeleton<'s>(
+    skin: &gltf::Skin,
+    parents: &[Option<usize>],
+    globals: &[Matrix4<f32>],
+    buffer_data: impl Clone + Fn(gltf::Buffer) -> Option<&'s [u8]>,
+) -> anyhow::Result<Skeleton> {
+    let nodes: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+    // The nearest ancestor that is a joint as well
+    let parent_joint = |node: usize| {
+        let mut ancestor = parents[node];
+        while let Some(node) = ancestor {
+            if let Some(joint) = nodes.iter().position(|&joint| joint == node) {
+                return Some(joint);
+            }
+            ancestor = parents[node];
+        }
+        None
+    };
+    let inverse_binds: Vec<Matrix4<f32>> =
+        match skin.reader(buffer_data).read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Matrix4::from).collect(),
+            None => vec![Matrix4::identity(); nodes.len()],
+        };
+    anyhow::ensure!(
+        inverse_binds.len() == nodes.len(),
+        "Skin {} has {} joints but {} inverse bind matrices",
+        skin.index(),
+        nodes.len(),
+        inverse_binds.len()
+    );
+
+    let joints = skin
+        .joints()
+        .zip(inverse_binds)
+        .map(|(node, inverse_bind)| {
+            let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+            Joint {
+                name: node
+                    .name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("joint {}", node.index())),
+                parent: parent_joint(node.index()),
+                rest: Transform {
+                    translation: translation.into(),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                },
+                inverse_bind,
             }
         })
         .collect::<Vec<_>>();
+    // Nodes above the skeleton move all of it
+    let root = joints
+        .iter()
+        .zip(&nodes)
+        .find(|(joint, _)| joint.parent.is_none())
+        .and_then(|(_, &node)| parents[node])
+        .map_or_else(Matrix4::identity, |parent| globals[parent]);
+    Skeleton::new(joints, root)
+}
+
+/// Every animation of the file, only keeping the channels that move joints of `skin`.
+fn load_animations<'s>(
+    gltf: &gltf::Document,
+    skin: &gltf::Skin,
+    buffer_data: impl Clone + Fn(gltf::Buffer) -> Option<&'s [u8]>,
+) -> anyhow::Result<Vec<AnimationClip>> {
+    let joints: HashMap<usize, usize> = skin
+        .joints()
+        .enumerate()
+        .map(|(joint, node)| (node.index(), joint))
+        .collect();
+    // Cubic splines store an in tangent, the value and an out tangent for every keyframe.
+    // Only the values are kept and blended linearly.
+    fn values<T>(values: impl Iterator<Item = T>, cubic: bool) -> Vec<T> {
+        if cubic {
+            values.skip(1).step_by(3).collect()
+        } else {
+            values.collect()
+        }
+    }
+
+    let mut animations = Vec::new();
+    for animation in gltf.animations() {
+        let mut channels = Vec::new();
+        let mut duration = 0.0f32;
+        for channel in animation.channels() {
+            let Some(&joint) = joints.get(&channel.target().node().index()) else {
+                continue;
+            };
+            let reader = channel.reader(buffer_data.clone());
+            let times: Vec<f32> = reader
+                .read_inputs()
+                .context("Animation channel without keyframe times")?
+                .collect();
+            let (interpolation, cubic) = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => (Interpolation::Step, false),
+                gltf::animation::Interpolation::Linear => (Interpolation::Linear, false),
+                gltf::animation::Interpolation::CubicSpline => (Interpolation::Linear, true),
+            };
+            use gltf::animation::util::ReadOutputs;
+            let keyframes = match reader
+                .read_outputs()
+                .context("Animation channel without keyframe values")?
+            {
+                ReadOutputs::Translations(translations) => {
+                    Keyframes::Translation(values(translations.map(Vector3::from), cubic))
+                }
+                ReadOutputs::Rotations(rotations) => Keyframes::Rotation(values(
+                    rotations
+                        .into_f32()
+                        .map(|[x, y, z, w]| Quaternion::new(w, x, y, z)),
+                    cubic,
+                )),
+                ReadOutputs::Scales(scales) => {
+                    Keyframes::Scale(values(scales.map(Vector3::from), cubic))
+                }
+                // Morph targets are not supported
+                ReadOutputs::MorphTargetWeights(_) => continue,
+            };
+            let count = match &keyframes {
+                Keyframes::Translation(values) | Keyframes::Scale(values) => values.len(),
+                Keyframes::Rotation(values) => values.len(),
+            };
+            anyhow::ensure!(
+                count == times.len(),
+                "Animation {} has {} keyframe times but {} values",
+                animation.index(),
+                times.len(),
+                count
+            );
+            duration = times.iter().copied().fold(duration, f32::max);
+            channels.push(Channel {
+                joint,
+                interpolation,
+                times,
+                keyframes,
+            });
+        }
+        animations.push(AnimationClip {
+            name: animation
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("animation {}", animation.index())),
+            duration,
+            channels,
+        });
+    }
+    Ok(animations)
+}
+
+/// Computes per vertex tangents and bitangents for normal mapping by averaging
+/// the tangent space of every triangle a vertex is part of. Vertices whose triangles
+/// have no usable texture coordinates get any basis around their normal instead.
+fn calculate_tangents(vertices: &mut [model::ModelVertex], indices: &[u32]) {
+    let mut triangles_included = vec![0; vertices.len()];
+
+    for c in indices.chunks(3) {
+        let v0 = vertices[c[0] as usize];
+        let v1 = vertices[c[1] as usize];
+        let v2 = vertices[c[2] as usize];
+
+        let pos0: cgmath::Vector3<_> = v0.position.into();
+        let pos1: cgmath::Vector3<_> = v1.position.into();
+        let pos2: cgmath::Vector3<_> = v2.position.into();
+
+        let uv0: cgmath::Vector2<_> = v0.tex_coords.into();
+        let uv1: cgmath::Vector2<_> = v1.tex_coords.into();
+        let uv2: cgmath::Vector2<_> = v2.tex_coords.into();
+
+        // Edges of the triangle in model space and in texture space
+        let delta_pos1 = pos1 - pos0;
+        let delta_pos2 = pos2 - pos0;
+        let delta_uv1 = uv1 - uv0;
+        let delta_uv2 = uv2 - uv0;
+
+        // Solving
+        //     delta_pos1 = delta_uv1.x * T + delta_uv1.y * B
+        //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+        // for T and B. The bitangent is flipped because wgpu's texture
+        // coordinates grow downwards.
+        let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
+        if !r.is_finite() {
+            // Degenerate texture coordinates, the normal map cannot be oriented
+            continue;
+        }
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+        for &i in c {
+            let vertex = &mut vertices[i as usize];
+            vertex.tangent = (tangent + cgmath::Vector3::from(vertex.tangent)).into();
+            vertex.bitangent = (bitangent + cgmath::Vector3::from(vertex.bitangent)).into();
+            triangles_included[i as usize] += 1;
+        }
+    }
+
+    for (i, n) in triangles_included.into_iter().enumerate() {
+        let vertex = &mut vertices[i];
+        let normal = cgmath::Vector3::from(vertex.normal);
+        let tangent = cgmath::Vector3::from(vertex.tangent);
+        // The shader normalizes the tangent, one of zero length shades everything as NaN
+        if n == 0 || tangent.cross(normal).magnitude2() < 1e-12 {
+            let (tangent, bitangent) = orthogonal_basis(normal);
+            vertex.tangent = tangent.into();
+            vertex.bitangent = bitangent.into();
+            continue;
+        }
+        let denom = 1.0 / n as f32;
+        vertex.tangent = (tangent * denom).into();
+        vertex.bitangent = (cgmath::Vector3::from(vertex.bitangent) * denom).into();
+    }
+}
 
-    Ok(model::Model { meshes, materials })
+/// A tangent and bitangent at right angles to `normal` and each other. Only right for
+/// flat normal maps, which are all that untextured materials have.
+fn orthogonal_basis(normal: cgmath::Vector3<f32>) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+    let normal = normal.normalize();
+    // Any axis that is not too close to the normal
+    let axis = if normal.x.abs() < 0.9 {
+        cgmath::Vector3::unit_x()
+    } else {
+        cgmath::Vector3::unit_y()
+    };
+    let tangent = (axis - normal * axis.dot(normal)).normalize();
+    (tangent, normal.cross(tangent))
 }
@@ -0,0 +1,55 @@
+use anyhow::*;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+/// Directory screenshots are written to, relative to the working directory
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Writes `image` to a PNG named after the current UTC time, e.g.
+/// `screenshots/2023-04-01_12-30-05.123.png`.
+pub fn save(image: &RgbaImage) -> Result<PathBuf> {
+    let dir = Path::new(SCREENSHOT_DIR);
+    std::fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    let path = dir.join(format!("{}.png", timestamp(std::time::SystemTime::now())));
+    image
+        .save(&path)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The name of a screenshot taken at `time` without the extension, e.g.
+/// `2023-04-01_12-30-05.123`.
+pub fn timestamp(time: std::time::SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}.{:03}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date in the proleptic
+/// Gregorian calendar. See http://howardhinnant.github.io/date_algorithms.html
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -13,39 +13,127 @@ struct Light {
 @group(2) @binding(0)
 var<uniform> light: Light;
 
+struct Shadow {
+    cascades: array<mat4x4<f32>, 4>,
+    splits: vec4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    // x: depth bias, y: normal offset, w: texel size
+    bias: vec4<f32>,
+}
+@group(2) @binding(1)
+var<uniform> shadow: Shadow;
+@group(2) @binding(2)
+var t_shadow: texture_depth_2d_array;
+@group(2) @binding(3)
+var s_shadow: sampler_comparison;
+
+struct Sky {
+    inv_view_proj: mat4x4<f32>,
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    sun_direction: vec4<f32>,
+    // x: fog start, y: fog end
+    fog: vec4<f32>,
+}
+@group(2) @binding(4)
+var<uniform> sky: Sky;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
 }
 struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+    @location(12) uv_rect: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+    @location(5) view_depth: f32,
 }
 
+struct SkinInput {
+    @location(13) joints: vec4<u32>,
+    @location(14) weights: vec4<f32>,
+}
+
+// Has to match MAX_JOINTS in bind_group_layouts.rs
+struct Skin {
+    joints: array<mat4x4<f32>, 64>,
+}
+@group(3) @binding(0)
+var<uniform> skin: Skin;
+
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
+    return transform_vertex(model, instance);
+}
+
+// Moves the vertex along with the joints it is bound to before placing the model
+@vertex
+fn vs_skinned(
+    model: VertexInput,
+    instance: InstanceInput,
+    bones: SkinInput,
+) -> VertexOutput {
+    let skin_matrix = skin.joints[bones.joints.x] * bones.weights.x
+        + skin.joints[bones.joints.y] * bones.weights.y
+        + skin.joints[bones.joints.z] * bones.weights.z
+        + skin.joints[bones.joints.w] * bones.weights.w;
+    // Joints are only rotated and scaled uniformly, so this keeps normals perpendicular
+    let skin_normal = mat3x3<f32>(skin_matrix[0].xyz, skin_matrix[1].xyz, skin_matrix[2].xyz);
+
+    var skinned = model;
+    skinned.position = (skin_matrix * vec4<f32>(model.position, 1.0)).xyz;
+    skinned.normal = normalize(skin_normal * model.normal);
+    skinned.tangent = skin_normal * model.tangent;
+    skinned.bitangent = skin_normal * model.bitangent;
+    return transform_vertex(skinned, instance);
+}
+
+fn transform_vertex(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
 
     let world_position = model_matrix * vec4<f32>(model.position, 1.0);
 
     var out: VertexOutput;
     out.clip_position = camera.view_proj * world_position;
-    out.tex_coords = model.tex_coords;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_position = world_position.xyz;
+    // For a perspective projection w holds the distance along the view direction
+    out.view_depth = out.clip_position.w;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
+    // Map the mesh coordinates into the instance's atlas region
+    out.tex_coords = mix(instance.uv_rect.xy, instance.uv_rect.zw, model.tex_coords);
 
     return out;
 }
@@ -56,9 +144,95 @@ fn vs_main(
 var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+
+// Fraction of the sun light reaching the surface, filtered over 3x3 shadow map texels
+fn sun_visibility(world_position: vec3<f32>, world_normal: vec3<f32>, view_depth: f32) -> f32 {
+    var cascade = 0;
+    for (var i = 0; i < 3; i++) {
+        if view_depth > shadow.splits[i] {
+            cascade = i + 1;
+        }
+    }
+
+    let offset_position = world_position + world_normal * shadow.bias.y;
+    let light_space = shadow.cascades[cascade] * vec4<f32>(offset_position, 1.0);
+    let projected = light_space.xyz / light_space.w;
+    let uv = projected.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if view_depth > shadow.splits[3] || projected.z > 1.0 || any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return 1.0;
+    }
+
+    let depth = projected.z - shadow.bias.x;
+    var visibility = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.bias.w;
+            visibility += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, cascade, depth);
+        }
+    }
+    return visibility / 9.0;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in);
+}
+
+// Alpha tested variant for blocks with holes, like leaves
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(in);
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
     let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    return object_color * vec4(1.0,0.0,0.0,1.0);
+
+    // Blinn-Phong: a constant ambient term plus diffuse and specular from the light
+    let ambient_strength = 0.1;
+    let ambient_color = light.color * ambient_strength;
+
+    // Rotate the normal from the normal map out of tangent space into world space
+    let object_normal: vec4<f32> = textureSample(t_normal, s_normal, in.tex_coords);
+    let tangent_matrix = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    let tangent_normal = object_normal.xyz * 2.0 - 1.0;
+    let world_normal = normalize(tangent_matrix * tangent_normal);
+    let light_dir = normalize(light.position - in.world_position);
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+    let half_dir = normalize(view_dir + light_dir);
+
+    let diffuse_strength = max(dot(world_normal, light_dir), 0.0);
+    let diffuse_color = light.color * diffuse_strength;
+
+    let specular_strength = pow(max(dot(world_normal, half_dir), 0.0), 32.0);
+    let specular_color = specular_strength * light.color;
+
+    // Directional light from the sun, darkened where the shadow map is occluded
+    let sun_dir = -normalize(shadow.sun_direction.xyz);
+    let sun_half_dir = normalize(view_dir + sun_dir);
+    let sun_strength = max(dot(world_normal, sun_dir), 0.0)
+        + pow(max(dot(world_normal, sun_half_dir), 0.0), 32.0);
+    let geometry_normal = normalize(in.world_normal);
+    let sun_color = shadow.sun_color.xyz * sun_strength
+        * sun_visibility(in.world_position, geometry_normal, in.view_depth);
+
+    let result = (ambient_color + diffuse_color + specular_color + sun_color) * object_color.xyz;
+
+    // Fade into the sky towards the far plane to hide where the world ends
+    let distance = length(in.world_position - camera.view_pos.xyz);
+    let fog = smoothstep(sky.fog.x, sky.fog.y, distance);
+    let fogged = mix(result, sky.horizon_color.xyz, fog);
+
+    return vec4<f32>(fogged, object_color.a);
 }
\ No newline at end of file
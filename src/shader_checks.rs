@@ -0,0 +1,46 @@
+//! Validation of the WGSL shaders, shared by build.rs and the hot reload of the renderer
+//! so a shader is held to the same checks in both places. build.rs includes this file
+//! next to `vertex_layouts` and `bind_group_layouts`, so it may only depend on those.
+
+use crate::{bind_group_layouts, vertex_layouts};
+
+/// Parses and validates the WGSL in `source` with naga, the error contains the annotated
+/// source. Vertex shader inputs are checked against the Rust vertex layouts and every
+/// resource binding against the bind group layouts of the file called `file_name`.
+pub fn validate(path: &str, file_name: &str, source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string_with_path(source, path))?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string_with_path(source, path))?;
+
+    let vertex_shaders: Vec<_> = vertex_layouts::VERTEX_SHADERS
+        .iter()
+        .filter(|(shader, _, _)| *shader == file_name)
+        .collect();
+    for (_, entry_point, buffers) in &vertex_shaders {
+        vertex_layouts::check_vertex_inputs(&module, entry_point, buffers)
+            .map_err(|e| format!("{} ({}): {}", path, entry_point, e))?;
+    }
+    // An entry point without buffers in the table would not be checked at all
+    if let Some(entry_point) = module.entry_points.iter().find(|e| {
+        e.stage == naga::ShaderStage::Vertex
+            && vertex_shaders.iter().all(|(_, name, _)| *name != e.name)
+    }) {
+        return Err(format!(
+            "{}: vertex entry point `{}` has no vertex buffers in vertex_layouts.rs",
+            path, entry_point.name
+        ));
+    }
+    for (_, groups) in bind_group_layouts::SHADER_BIND_GROUPS
+        .iter()
+        .filter(|(shader, _)| *shader == file_name)
+    {
+        bind_group_layouts::check_bind_groups(&module, &info, groups)
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
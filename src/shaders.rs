@@ -0,0 +1,147 @@
+use anyhow::*;
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Set to watch the WGSL files in `src/` and reload them while the game is running
+pub const HOT_RELOAD_ENV: &str = "SHADER_HOT_RELOAD";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Every WGSL shader the renderer uses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Shader {
+    Block,
+    Light,
+    Shadow,
+    Sky,
+    Outline,
+    Overlay,
+    Particle,
+}
+
+impl Shader {
+    pub const ALL: [Shader; 7] = [
+        Shader::Block,
+        Shader::Light,
+        Shader::Shadow,
+        Shader::Sky,
+        Shader::Outline,
+        Shader::Overlay,
+        Shader::Particle,
+    ];
+
+    /// File name in `src/`, the same file is baked into the binary with `include_str!`.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Shader::Block => "shader.wgsl",
+            Shader::Light => "light.wgsl",
+            Shader::Shadow => "shadow.wgsl",
+            Shader::Sky => "sky.wgsl",
+            Shader::Outline => "outline.wgsl",
+            Shader::Overlay => "overlay.wgsl",
+            Shader::Particle => "particle.wgsl",
+        }
+    }
+}
+
+/// Replacement source for every shader a resource pack or the asset root has in its
+/// `shaders/` directory. All other shaders keep the source baked into the binary.
+pub fn overrides(sources: &crate::resources::AssetSources) -> Vec<(Shader, Result<String>)> {
+    Shader::ALL
+        .into_iter()
+        .filter_map(|shader| {
+            let path = sources.find(&format!("shaders/{}", shader.file_name()))?;
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Could not read {}", path.display()));
+            Some((shader, source))
+        })
+        .collect()
+}
+
+/// Parses and validates WGSL with naga, the error contains the annotated source. The
+/// shader is also checked against the Rust vertex and bind group layouts, just like in
+/// build.rs.
+pub fn validate(shader: Shader, source: &str) -> Result<()> {
+    crate::shader_checks::validate(shader.file_name(), shader.file_name(), source)
+        .map_err(|e| anyhow!(e))
+}
+
+/// Runs `create` and turns any wgpu validation error it causes into an `Err` instead of
+/// the default panic, e.g. when a changed shader no longer matches its pipeline layout.
+pub fn try_create<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    let error = device.pop_error_scope();
+    // Native backends resolve error scopes immediately
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    let mut error = std::pin::pin!(error);
+    match error.as_mut().poll(&mut context) {
+        std::task::Poll::Ready(Some(error)) => bail!("{}", error),
+        _ => Ok(value),
+    }
+}
+
+/// Polls the modification times of the shader files and hands out the new source of
+/// every shader that changed on disk.
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    modified: HashMap<Shader, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: PathBuf) -> Self {
+        let mut watcher = Self {
+            dir,
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        // The baked in shaders are assumed to match what is on disk at startup
+        for shader in Shader::ALL {
+            if let Some(modified) = watcher.modified_time(shader) {
+                watcher.modified.insert(shader, modified);
+            }
+        }
+        watcher
+    }
+
+    /// Watches the shaders in the source tree if [`HOT_RELOAD_ENV`] is set.
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os(HOT_RELOAD_ENV)?;
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src");
+        log::info!("Watching {} for shader changes", dir.display());
+        Some(Self::new(dir))
+    }
+
+    fn modified_time(&self, shader: Shader) -> Option<SystemTime> {
+        std::fs::metadata(self.dir.join(shader.file_name()))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Shaders whose file changed since the last call, with their new source.
+    pub fn changed(&mut self) -> Vec<(Shader, Result<String>)> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for shader in Shader::ALL {
+            let Some(modified) = self.modified_time(shader) else {
+                continue;
+            };
+            if self.modified.insert(shader, modified) == Some(modified) {
+                continue;
+            }
+            let path = self.dir.join(shader.file_name());
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Could not read {}", path.display()));
+            changed.push((shader, source));
+        }
+        changed
+    }
+}
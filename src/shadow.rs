@@ -0,0 +1,500 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3, Vector4};
+use std::num::NonZeroU32;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group_layouts,
+    camera::{Projection, OPENGL_TO_WGPU_MATRIX},
+    model::Vertex,
+};
+
+/// Has to match the size of the `cascades` array in `shader.wgsl`.
+pub const CASCADE_COUNT: usize = 4;
+
+pub struct ShadowSettings {
+    // Width and height of every cascade in texels
+    pub map_size: u32,
+    // Blend between uniform (0.0) and logarithmic (1.0) cascade splits
+    pub split_lambda: f32,
+    // How far behind a cascade occluders are still rendered into it
+    pub caster_distance: f32,
+    // Distance from the camera the cascades cover, anything beyond is unshadowed.
+    // Keeps the shadows sharp when the far plane is moved out for a large render distance.
+    pub max_distance: f32,
+    // Hardware depth bias applied while rendering the shadow map
+    pub depth_bias_constant: i32,
+    pub depth_bias_slope: f32,
+    // Bias applied in the main shader when comparing depths
+    pub depth_bias: f32,
+    // World space offset along the surface normal before looking up the shadow map
+    pub normal_offset: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            map_size: 2048,
+            split_lambda: 0.75,
+            caster_distance: 100.0,
+            max_distance: 200.0,
+            depth_bias_constant: 2,
+            depth_bias_slope: 2.0,
+            depth_bias: 0.0005,
+            normal_offset: 0.05,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    cascades: [[[f32; 4]; 4]; CASCADE_COUNT],
+    // View space depth at which each cascade ends
+    splits: [f32; 4],
+    // Direction the sun light travels in, w is unused
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    // x: depth bias, y: normal offset, z: unused, w: size of one shadow map texel in uv
+    bias: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CascadeUniform {
+    light_view_proj: [[f32; 4]; 4],
+}
+
+pub struct ShadowMap {
+    pub settings: ShadowSettings,
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: Vector3<f32>,
+    uniform: ShadowUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    cascade_views: Vec<wgpu::TextureView>,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    pipeline_layout: wgpu::PipelineLayout,
+    // Adds the joint matrices of skinned models
+    skinned_pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    skinned_pipeline: wgpu::RenderPipeline,
+}
+
+impl ShadowMap {
+    pub fn new(
+        device: &wgpu::Device,
+        settings: ShadowSettings,
+        skin_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow_map"),
+            size: wgpu::Extent3d {
+                width: settings.map_size,
+                height: settings.map_size,
+                depth_or_array_layers: CASCADE_COUNT as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: crate::texture::Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("shadow_map_view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // Each cascade is rendered on its own, so every layer needs a separate view
+        let cascade_views = (0..CASCADE_COUNT as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("shadow_cascade_view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let sun_direction = Vector3::new(-0.4, -1.0, -0.3).normalize();
+        let sun_color = Vector3::new(1.0, 0.95, 0.85);
+        let uniform = ShadowUniform {
+            cascades: [Matrix4::identity().into(); CASCADE_COUNT],
+            splits: [0.0; 4],
+            sun_direction: sun_direction.extend(0.0).into(),
+            sun_color: sun_color.extend(1.0).into(),
+            bias: [
+                settings.depth_bias,
+                settings.normal_offset,
+                0.0,
+                1.0 / settings.map_size as f32,
+            ],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let cascade_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &bind_group_layouts::SHADOW_CASCADE_ENTRIES,
+                label: Some("shadow_cascade_bind_group_layout"),
+            });
+        let cascade_buffers = (0..CASCADE_COUNT)
+            .map(|_| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Shadow Cascade Buffer"),
+                    contents: bytemuck::cast_slice(&[CascadeUniform {
+                        light_view_proj: Matrix4::identity().into(),
+                    }]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect::<Vec<_>>();
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &cascade_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("shadow_cascade_bind_group"),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&cascade_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let skinned_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skinned Shadow Pipeline Layout"),
+                bind_group_layouts: &[&cascade_bind_group_layout, skin_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let (pipeline, skinned_pipeline) = ShadowMap::create_pipelines(
+            device,
+            &pipeline_layout,
+            &skinned_pipeline_layout,
+            &settings,
+            include_str!("shadow.wgsl"),
+        );
+
+        Self {
+            settings,
+            sun_direction,
+            sun_color,
+            uniform,
+            uniform_buffer,
+            view,
+            sampler,
+            cascade_views,
+            cascade_buffers,
+            cascade_bind_groups,
+            pipeline_layout,
+            skinned_pipeline_layout,
+            pipeline,
+            skinned_pipeline,
+        }
+    }
+
+    /// The pipeline for static meshes and the one for skinned meshes.
+    fn create_pipelines(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        skinned_layout: &wgpu::PipelineLayout,
+        settings: &ShadowSettings,
+        source: &str,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipeline = |layout, entry_point, buffers: &[wgpu::VertexBufferLayout]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Shadow Pipeline"),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point,
+                    buffers,
+                },
+                // Only depth is written
+                fragment: None,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: crate::texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState {
+                        constant: settings.depth_bias_constant,
+                        slope_scale: settings.depth_bias_slope,
+                        clamp: 0.0,
+                    },
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        (
+            pipeline(
+                layout,
+                "vs_main",
+                &[
+                    crate::model::ModelVertex::desc(),
+                    crate::InstanceRaw::desc(),
+                ],
+            ),
+            pipeline(
+                skinned_layout,
+                "vs_skinned",
+                &[
+                    crate::model::ModelVertex::desc(),
+                    crate::InstanceRaw::desc(),
+                    crate::model::SkinVertex::desc(),
+                ],
+            ),
+        )
+    }
+
+    /// Replaces the pipelines with ones built from `source`, keeping the old ones on errors.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> anyhow::Result<()> {
+        (self.pipeline, self.skinned_pipeline) = crate::shaders::try_create(device, || {
+            ShadowMap::create_pipelines(
+                device,
+                &self.pipeline_layout,
+                &self.skinned_pipeline_layout,
+                &self.settings,
+                source,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Fits every cascade around its slice of the camera frustum and uploads the matrices.
+    pub fn update(&mut self, queue: &wgpu::Queue, view: Matrix4<f32>, projection: &Projection) {
+        let splits = cascade_splits(
+            projection.znear(),
+            projection.zfar().min(self.settings.max_distance),
+            CASCADE_COUNT,
+            self.settings.split_lambda,
+        );
+
+        let mut near = projection.znear();
+        for (i, &far) in splits.iter().enumerate() {
+            let matrix = cascade_matrix(
+                view,
+                projection.fovy(),
+                projection.aspect(),
+                near..far,
+                self.sun_direction,
+                self.settings.caster_distance,
+                self.settings.map_size,
+            );
+            self.uniform.cascades[i] = matrix.into();
+            self.uniform.splits[i] = far;
+            queue.write_buffer(
+                &self.cascade_buffers[i],
+                0,
+                bytemuck::cast_slice(&[CascadeUniform {
+                    light_view_proj: matrix.into(),
+                }]),
+            );
+            near = far;
+        }
+
+        self.uniform.sun_direction = self.sun_direction.normalize().extend(0.0).into();
+        self.uniform.sun_color = self.sun_color.extend(1.0).into();
+        self.uniform.bias = [
+            self.settings.depth_bias,
+            self.settings.normal_offset,
+            0.0,
+            1.0 / self.settings.map_size as f32,
+        ];
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Bytes in the uniform buffers of the shadow map.
+    pub fn buffer_memory(&self) -> u64 {
+        self.uniform_buffer.size()
+            + self
+                .cascade_buffers
+                .iter()
+                .map(|buffer| buffer.size())
+                .sum::<u64>()
+    }
+
+    /// Renders the depth of every model with its range of `instance_buffer` as seen from
+    /// the sun into every cascade. Skinned models come with the offset of the joint
+    /// matrices of their first instance in the buffer bound by `skin_bind_group`, the
+    /// matrices of the other instances follow it `palette_size` bytes apart.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &[(&crate::model::Model, std::ops::Range<u32>, Option<u32>)],
+        instance_buffer: &wgpu::Buffer,
+        skin_bind_group: Option<&wgpu::BindGroup>,
+        palette_size: u32,
+    ) {
+        for (view, bind_group) in self.cascade_views.iter().zip(&self.cascade_bind_groups) {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            shadow_pass.set_bind_group(0, bind_group, &[]);
+            shadow_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            for (model, instances, skin) in draws {
+                for mesh in &model.meshes {
+                    shadow_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    shadow_pass
+                        .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    match (&mesh.skin_buffer, skin, skin_bind_group) {
+                        (Some(skin_buffer), Some(offset), Some(skin_bind_group)) => {
+                            // Every instance has a pose of its own
+                            shadow_pass.set_pipeline(&self.skinned_pipeline);
+                            shadow_pass.set_vertex_buffer(2, skin_buffer.slice(..));
+                            for (i, instance) in instances.clone().enumerate() {
+                                shadow_pass.set_bind_group(
+                                    1,
+                                    skin_bind_group,
+                                    &[offset + i as u32 * palette_size],
+                                );
+                                shadow_pass.draw_indexed(
+                                    0..mesh.num_elements,
+                                    0,
+                                    instance..instance + 1,
+                                );
+                            }
+                        }
+                        _ => {
+                            shadow_pass.set_pipeline(&self.pipeline);
+                            shadow_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// View space depths at which each of `count` cascades ends, blending between a uniform
+/// and a logarithmic distribution of the range between `znear` and `zfar`.
+pub fn cascade_splits(znear: f32, zfar: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let p = i as f32 / count as f32;
+            let logarithmic = znear * (zfar / znear).powf(p);
+            let uniform = znear + (zfar - znear) * p;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Orthographic sun projection enclosing the part of the view frustum between `depth.start`
+/// and `depth.end`. The projection is fitted around a bounding sphere and snapped to whole
+/// shadow map texels so that shadows do not shimmer while the camera turns or moves.
+pub fn cascade_matrix(
+    view: Matrix4<f32>,
+    fovy: Rad<f32>,
+    aspect: f32,
+    depth: std::ops::Range<f32>,
+    sun_direction: Vector3<f32>,
+    caster_distance: f32,
+    map_size: u32,
+) -> Matrix4<f32> {
+    let inverse_view = view.invert().unwrap_or_else(Matrix4::identity);
+    let tan_half_fovy = (fovy.0 / 2.0).tan();
+
+    let mut corners = Vec::with_capacity(8);
+    for distance in [depth.start, depth.end] {
+        let height = distance * tan_half_fovy;
+        let width = height * aspect;
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            let corner = inverse_view * Vector4::new(x * width, y * height, -distance, 1.0);
+            corners.push(corner.truncate());
+        }
+    }
+
+    let center = corners
+        .iter()
+        .fold(Vector3::new(0.0, 0.0, 0.0), |a, b| a + b)
+        / 8.0;
+    let radius = corners
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max);
+    // Rounding keeps the projection size constant while the camera rotates
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = sun_direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let eye = Point3::from_vec(center - direction * (radius + caster_distance));
+    let light_view = Matrix4::look_to_rh(eye, direction, up);
+    let mut light_projection = cgmath::ortho(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + caster_distance,
+    );
+
+    let origin = light_projection * light_view * Vector4::new(0.0, 0.0, 0.0, 1.0);
+    let texels = origin.truncate().truncate() * (map_size as f32 / 2.0);
+    let offset = texels.map(f32::round) - texels;
+    light_projection.w.x += offset.x * 2.0 / map_size as f32;
+    light_projection.w.y += offset.y * 2.0 / map_size as f32;
+
+    OPENGL_TO_WGPU_MATRIX * light_projection * light_view
+}
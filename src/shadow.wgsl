@@ -0,0 +1,60 @@
+// Depth only pass rendering the scene from the sun into one shadow cascade
+
+struct Cascade {
+    light_view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> cascade: Cascade;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+struct SkinInput {
+    @location(13) joints: vec4<u32>,
+    @location(14) weights: vec4<f32>,
+}
+
+// Has to match MAX_JOINTS in bind_group_layouts.rs
+struct Skin {
+    joints: array<mat4x4<f32>, 64>,
+}
+@group(1) @binding(0)
+var<uniform> skin: Skin;
+
+fn project(position: vec3<f32>, instance: InstanceInput) -> vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return cascade.light_view_proj * model_matrix * vec4<f32>(position, 1.0);
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    return project(model.position, instance);
+}
+
+@vertex
+fn vs_skinned(
+    model: VertexInput,
+    instance: InstanceInput,
+    bones: SkinInput,
+) -> @builtin(position) vec4<f32> {
+    let skin_matrix = skin.joints[bones.joints.x] * bones.weights.x
+        + skin.joints[bones.joints.y] * bones.weights.y
+        + skin.joints[bones.joints.z] * bones.weights.z
+        + skin.joints[bones.joints.w] * bones.weights.w;
+    return project((skin_matrix * vec4<f32>(model.position, 1.0)).xyz, instance);
+}
@@ -0,0 +1,238 @@
+//! Skeletons and keyframe animations of skinned models. Everything here is plain data on
+//! the CPU: a pose is sampled from a clip and turned into one matrix per joint, which the
+//! skinned vertex shader blends between for every vertex.
+
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, SquareMatrix, Vector3, VectorSpace};
+
+pub use crate::bind_group_layouts::MAX_JOINTS;
+
+/// Position, orientation and size of a joint relative to its parent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    /// Scaled, then rotated, then moved.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    // Index of the parent joint, roots have none
+    pub parent: Option<usize>,
+    // Transform relative to the parent when the model is not animated
+    pub rest: Transform,
+    // Takes a vertex from model space into the space of the joint at bind time
+    pub inverse_bind: Matrix4<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    // Transform of the nodes above the root joints, applied to all of them
+    pub root: Matrix4<f32>,
+    // Joint indices with every parent before its children
+    order: Vec<usize>,
+}
+
+impl Skeleton {
+    /// Fails if there are too many joints for the shaders or the parents form a cycle.
+    pub fn new(joints: Vec<Joint>, root: Matrix4<f32>) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            joints.len() <= MAX_JOINTS,
+            "A skeleton with {} joints has more than the {} the shaders support",
+            joints.len(),
+            MAX_JOINTS
+        );
+        let depth = |mut joint: usize| {
+            let mut depth = 0;
+            while let Some(parent) = joints[joint].parent {
+                anyhow::ensure!(
+                    parent < joints.len() && depth < joints.len(),
+                    "Joint {} has an invalid parent",
+                    joints[joint].name
+                );
+                joint = parent;
+                depth += 1;
+            }
+            Ok(depth)
+        };
+        let mut order = (0..joints.len())
+            .map(|joint| Ok((depth(joint)?, joint)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        order.sort();
+        let order = order.into_iter().map(|(_, joint)| joint).collect();
+        Ok(Self {
+            joints,
+            root,
+            order,
+        })
+    }
+
+    /// The pose the model was modelled in.
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// Index of the joint called `name`.
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    /// One matrix per joint taking a vertex from its place in the model to where `pose`
+    /// moves it. The rest pose gives the identity for every joint.
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let mut global = vec![Matrix4::identity(); self.joints.len()];
+        for &joint in &self.order {
+            let parent = match self.joints[joint].parent {
+                Some(parent) => global[parent],
+                None => self.root,
+            };
+            global[joint] = parent * pose[joint].matrix();
+        }
+        global
+            .iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| global * joint.inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interpolation {
+    // Holds every keyframe until the next one
+    Step,
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// Keyframes for one property of one joint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    // Seconds of every keyframe in ascending order
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+impl Channel {
+    /// The keyframes around `time` and how far it is from the first to the second.
+    fn keyframes_at(&self, time: f32) -> (usize, usize, f32) {
+        let last = self.times.len() - 1;
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next > last {
+            return (last, last, 0.0);
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let blend = match self.interpolation {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => (time - start) / (end - start),
+        };
+        (next - 1, next, blend)
+    }
+
+    fn apply(&self, time: f32, transform: &mut Transform) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (a, b, blend) = self.keyframes_at(time);
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = values[a].lerp(values[b], blend)
+            }
+            Keyframes::Rotation(values) => {
+                // nlerp takes the short way round when the quaternions point apart
+                let (from, mut to) = (values[a], values[b]);
+                if from.dot(to) < 0.0 {
+                    to = -to;
+                }
+                transform.rotation = from.nlerp(to, blend)
+            }
+            Keyframes::Scale(values) => transform.scale = values[a].lerp(values[b], blend),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    // Seconds until the last keyframe
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Overwrites the animated properties of `pose` with their values `time` seconds into
+    /// the clip. Times outside the clip hold the first or last keyframe.
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            if let Some(transform) = pose.get_mut(channel.joint) {
+                channel.apply(time, transform);
+            }
+        }
+    }
+}
+
+/// Which clip of its model an entity plays and how far into it it is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnimationPlayer {
+    // Index into the animations of the model
+    pub clip: usize,
+    pub time: f32,
+    // Multiplier for the frame time, negative values play backwards
+    pub speed: f32,
+    // Start over at the end instead of holding the last pose
+    pub looping: bool,
+}
+
+impl AnimationPlayer {
+    /// Plays `clip` from the start in a loop.
+    pub fn new(clip: usize) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+        }
+    }
+
+    /// Moves on by `dt` seconds in a clip that lasts `duration`.
+    pub fn advance(&mut self, dt: f32, duration: f32) {
+        let time = self.time + dt * self.speed;
+        self.time = if duration <= 0.0 {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(duration)
+        } else {
+            time.clamp(0.0, duration)
+        };
+    }
+}
@@ -0,0 +1,243 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use std::f32::consts::TAU;
+use wgpu::util::DeviceExt;
+
+use crate::bind_group_layouts;
+
+/// Position of the sun over the course of a day. `time` runs from 0.0 to 1.0, where
+/// 0.0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset.
+pub struct TimeOfDay {
+    pub time: f32,
+    // Length of a full day in seconds
+    pub day_length: f32,
+}
+
+impl TimeOfDay {
+    pub fn new(time: f32, day_length: f32) -> Self {
+        Self { time, day_length }
+    }
+
+    pub fn advance(&mut self, dt: std::time::Duration) {
+        self.time = (self.time + dt.as_secs_f32() / self.day_length).rem_euclid(1.0);
+    }
+
+    /// Unit vector pointing from the world towards the sun.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.time - 0.25) * TAU;
+        // Slightly tilted so that the sun does not pass straight overhead
+        Vector3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+
+    /// The sun during the day, the moon at night.
+    /// Returns the direction the light travels in and its colour.
+    pub fn light(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let sun = self.sun_direction();
+        if sun.y > 0.0 {
+            let strength = smoothstep(0.0, 0.15, sun.y);
+            (-sun, Vector3::new(1.0, 0.95, 0.85) * strength)
+        } else {
+            let strength = smoothstep(0.0, 0.15, -sun.y);
+            (sun, Vector3::new(0.15, 0.17, 0.25) * strength)
+        }
+    }
+
+    /// Sky colour straight up and at the horizon, in linear space.
+    pub fn sky_colors(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let height = self.sun_direction().y;
+        let day = smoothstep(-0.2, 0.2, height);
+        let sunset = (1.0 - height.abs() / 0.3).clamp(0.0, 1.0);
+
+        let zenith = lerp(
+            Vector3::new(0.005, 0.007, 0.03),
+            Vector3::new(0.15, 0.35, 0.85),
+            day,
+        );
+        let horizon = lerp(
+            Vector3::new(0.02, 0.025, 0.06),
+            Vector3::new(0.55, 0.7, 0.95),
+            day,
+        );
+        let horizon = lerp(horizon, Vector3::new(0.9, 0.45, 0.2), sunset * 0.6);
+        (zenith, horizon)
+    }
+
+    /// Distant geometry fades into the horizon colour so it blends with the sky.
+    pub fn fog_color(&self) -> Vector3<f32> {
+        self.sky_colors().1
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: Vector3<f32>, b: Vector3<f32>, t: f32) -> Vector3<f32> {
+    a + (b - a) * t
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+    // Towards the sun, w is unused
+    sun_direction: [f32; 4],
+    // x: distance where fog starts, y: distance where everything is fog
+    fog: [f32; 4],
+}
+
+pub struct Sky {
+    pub time_of_day: TimeOfDay,
+    uniform: SkyUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    color_format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Sky {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+        let time_of_day = TimeOfDay::new(0.35, 600.0);
+        let uniform = SkyUniform {
+            inv_view_proj: Matrix4::identity().into(),
+            zenith_color: [0.0; 4],
+            horizon_color: [0.0; 4],
+            sun_direction: [0.0; 4],
+            fog: [0.0; 4],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &bind_group_layouts::SKY_ENTRIES,
+            label: Some("sky_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("sky_bind_group"),
+        });
+
+        let pipeline = Sky::create_pipeline(
+            device,
+            &bind_group_layout,
+            color_format,
+            include_str!("sky.wgsl"),
+        );
+
+        Self {
+            time_of_day,
+            uniform,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            color_format,
+            pipeline,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        source: &str,
+    ) -> wgpu::RenderPipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                // The full screen triangle is generated from the vertex index
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Drawn first behind everything, so it neither tests nor writes depth
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: crate::texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Replaces the pipeline with one built from `source`, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> anyhow::Result<()> {
+        self.pipeline = crate::shaders::try_create(device, || {
+            Sky::create_pipeline(device, &self.bind_group_layout, self.color_format, source)
+        })?;
+        Ok(())
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        view_proj: Matrix4<f32>,
+        projection: &crate::camera::Projection,
+    ) {
+        let (zenith, horizon) = self.time_of_day.sky_colors();
+        self.uniform.inv_view_proj = view_proj.invert().unwrap_or_else(Matrix4::identity).into();
+        self.uniform.zenith_color = zenith.extend(1.0).into();
+        self.uniform.horizon_color = horizon.extend(1.0).into();
+        self.uniform.sun_direction = self.time_of_day.sun_direction().extend(0.0).into();
+        // Fully fogged just before the far plane so blocks never visibly pop in or out
+        self.uniform.fog = [projection.zfar() * 0.5, projection.zfar() * 0.95, 0.0, 0.0];
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        let color = self.time_of_day.fog_color();
+        wgpu::Color {
+            r: color.x as f64,
+            g: color.y as f64,
+            b: color.z as f64,
+            a: 1.0,
+        }
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,55 @@
+// Full screen gradient sky with a sun and a moon
+
+struct Sky {
+    inv_view_proj: mat4x4<f32>,
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    sun_direction: vec4<f32>,
+    fog: vec4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // A single triangle covering the whole screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Reconstruct the direction of the view ray through this pixel
+    let near = sky.inv_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = sky.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let ray = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    let height = clamp(ray.y, 0.0, 1.0);
+    var color = mix(sky.horizon_color.xyz, sky.zenith_color.xyz, sqrt(height));
+
+    let sun_dir = normalize(sky.sun_direction.xyz);
+    let sun_dot = dot(ray, sun_dir);
+    let sun_disc = smoothstep(0.9985, 0.9992, sun_dot);
+    let sun_glow = pow(max(sun_dot, 0.0), 64.0) * 0.3;
+    color += vec3<f32>(1.0, 0.9, 0.7) * (sun_disc * 4.0 + sun_glow);
+
+    let moon_disc = smoothstep(0.9993, 0.9996, dot(ray, -sun_dir));
+    color = mix(color, vec3<f32>(0.8, 0.82, 0.9), moon_disc);
+
+    // Below the horizon the sky darkens towards the ground
+    if ray.y < 0.0 {
+        color = mix(sky.horizon_color.xyz, sky.horizon_color.xyz * 0.4, clamp(-ray.y * 4.0, 0.0, 1.0));
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,159 @@
+use anyhow::*;
+use std::collections::HashMap;
+
+use crate::{
+    atlas::{Atlas, AtlasBuilder},
+    overlay::{Overlay, Rect, SpriteTexture},
+    renderer::Renderer,
+};
+
+/// Characters rasterized into the glyph atlas, anything else is drawn as `?`
+const CHARACTERS: std::ops::RangeInclusive<char> = ' '..='~';
+const FALLBACK: char = '?';
+
+#[derive(Debug, Copy, Clone)]
+struct Glyph {
+    // Offset of the bitmap from the pen position on the baseline, y pointing down
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+    advance: f32,
+    // `None` for glyphs without pixels, like the space
+    uv: Option<[f32; 4]>,
+}
+
+/// A font rasterized at a single pixel size into a glyph atlas, drawn through the overlay.
+pub struct Font {
+    glyphs: HashMap<char, Glyph>,
+    ascent: f32,
+    line_height: f32,
+    texture: SpriteTexture,
+}
+
+impl Font {
+    /// Rasterizes the printable ASCII characters of a TTF or OTF font at `size` pixels.
+    pub fn new(renderer: &mut Renderer, font_data: &[u8], size: f32) -> Result<Self> {
+        let font = fontdue::Font::from_bytes(font_data, fontdue::FontSettings::default())
+            .map_err(|e| anyhow!("Could not parse font: {}", e))?;
+        let line_metrics = font
+            .horizontal_line_metrics(size)
+            .context("Font has no horizontal line metrics")?;
+
+        let mut builder = AtlasBuilder::new(1);
+        let mut metrics = Vec::new();
+        for character in CHARACTERS {
+            let (glyph_metrics, coverage) = font.rasterize(character, size);
+            if glyph_metrics.width > 0 && glyph_metrics.height > 0 {
+                // White glyphs so that the sprite colour decides the text colour
+                let image = image::RgbaImage::from_fn(
+                    glyph_metrics.width as u32,
+                    glyph_metrics.height as u32,
+                    |x, y| {
+                        let alpha = coverage[y as usize * glyph_metrics.width + x as usize];
+                        image::Rgba([255, 255, 255, alpha])
+                    },
+                );
+                builder.add(&character.to_string(), image);
+            }
+            metrics.push((character, glyph_metrics));
+        }
+        let atlas = builder.build()?;
+
+        let glyphs = metrics
+            .into_iter()
+            .map(|(character, metrics)| {
+                let glyph = Glyph {
+                    left: metrics.xmin as f32,
+                    top: -(metrics.ymin as f32 + metrics.height as f32),
+                    width: metrics.width as f32,
+                    height: metrics.height as f32,
+                    advance: metrics.advance_width,
+                    uv: atlas.region(&character.to_string()).map(|region| region.uv),
+                };
+                (character, glyph)
+            })
+            .collect();
+
+        Ok(Self {
+            glyphs,
+            ascent: line_metrics.ascent,
+            line_height: line_metrics.new_line_size.ceil(),
+            texture: Font::upload(renderer, &atlas)?,
+        })
+    }
+
+    fn upload(renderer: &mut Renderer, atlas: &Atlas) -> Result<SpriteTexture> {
+        // Text is drawn pixel for pixel, it never needs smaller mip levels
+        renderer.overlay.add_texture(
+            &renderer.device,
+            &renderer.queue,
+            &atlas.mips[..1],
+            "font_atlas",
+        )
+    }
+
+    fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&character)
+            .or_else(|| self.glyphs.get(&FALLBACK))
+    }
+
+    /// Width and height in pixels `text` takes up when drawn.
+    pub fn measure(&self, text: &str) -> (f32, f32) {
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            let line_width = line
+                .chars()
+                .filter_map(|character| self.glyph(character))
+                .map(|glyph| glyph.advance)
+                .sum::<f32>();
+            width = width.max(line_width.round());
+            lines += 1;
+        }
+        (width, lines as f32 * self.line_height)
+    }
+
+    /// Queues `text` with its top left corner at (`x`, `y`). `\n` starts a new line.
+    pub fn draw(&self, overlay: &mut Overlay, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        self.draw_spans(overlay, x, y, &[(text, color)]);
+    }
+
+    /// Like [`Font::draw`], with every span of the text in its own colour.
+    /// Returns the position following the last character.
+    pub fn draw_spans(
+        &self,
+        overlay: &mut Overlay,
+        x: f32,
+        y: f32,
+        spans: &[(&str, [f32; 4])],
+    ) -> (f32, f32) {
+        // Glyphs are only crisp if they start on whole pixels
+        let (x, y) = (x.round(), y.round());
+        let (mut pen_x, mut pen_y) = (x, y);
+        for (text, color) in spans {
+            for character in text.chars() {
+                if character == '\n' {
+                    pen_x = x;
+                    pen_y += self.line_height;
+                    continue;
+                }
+                let Some(glyph) = self.glyph(character) else {
+                    continue;
+                };
+                if let Some(uv) = glyph.uv {
+                    let rect = Rect::new(
+                        pen_x.round() + glyph.left,
+                        pen_y + (self.ascent + glyph.top).round(),
+                        glyph.width,
+                        glyph.height,
+                    );
+                    overlay.sprite(self.texture, rect, uv, *color);
+                }
+                pen_x += glyph.advance;
+            }
+        }
+        (pen_x, pen_y)
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::*;
-use image::GenericImageView;
+use image::RgbaImage;
 use std::num::NonZeroU32;
 
+/// Tangent space normal (0, 0, 1) encoded as a normal map texel.
+pub const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -13,12 +16,13 @@ impl Texture {
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        width: u32,
+        height: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
@@ -53,6 +57,13 @@ impl Texture {
         }
     }
 
+    /// A 1x1 normal map pointing straight out of the surface, for materials without one.
+    pub fn flat_normal_map(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let img =
+            image::DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, image::Rgba(FLAT_NORMAL)));
+        Self::from_image(device, queue, &img, Some("flat_normal_map"), true)
+    }
+
     #[allow(dead_code)]
     pub fn from_bytes(
         device: &wgpu::Device,
@@ -72,8 +83,23 @@ impl Texture {
         label: Option<&str>,
         is_normal_map: bool,
     ) -> Result<Self> {
-        let dimensions = img.dimensions();
-        let rgba = img.to_rgba8();
+        let mips = mip_chain(img.to_rgba8(), !is_normal_map);
+        Self::from_mip_chain(device, queue, &mips, label, is_normal_map)
+    }
+
+    /// Uploads `mips` as the levels of a single texture, starting with the full size image.
+    /// Every level has to be half the size of the previous one.
+    pub fn from_mip_chain(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mips: &[RgbaImage],
+        label: Option<&str>,
+        is_normal_map: bool,
+    ) -> Result<Self> {
+        let dimensions = mips
+            .first()
+            .context("A texture needs at least one mip level")?
+            .dimensions();
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -88,7 +114,7 @@ impl Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count: mips.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
@@ -96,30 +122,40 @@ impl Texture {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: NonZeroU32::new(dimensions.1),
-            },
-            size,
-        );
+        for (level, mip) in mips.iter().enumerate() {
+            let (width, height) = mip.dimensions();
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                mip,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * width),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Magnification stays blocky, minification blends between mip levels (trilinear).
+        // No anisotropic filtering, wgpu only allows it when every filter is linear, which
+        // would blur the pixels of close up blocks.
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
             address_mode_w: wgpu::AddressMode::Repeat,
             mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
 
@@ -129,4 +165,86 @@ impl Texture {
             sampler,
         })
     }
+
+    /// Overwrites part of mip `level` with `image`, its top left corner goes to (`x`, `y`).
+    pub fn write_region(&self, queue: &wgpu::Queue, level: u32, x: u32, y: u32, image: &RgbaImage) {
+        let (width, height) = image.dimensions();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: level,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+            },
+            image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * width),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+/// Halves `image` in both dimensions with a 2x2 box filter. Colour textures are
+/// averaged in linear space so that the smaller levels do not get darker.
+pub fn downsample(image: &RgbaImage, srgb: bool) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0.0f32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel((x * 2 + dx).min(width - 1), (y * 2 + dy).min(height - 1));
+            for (channel, value) in sum.iter_mut().enumerate() {
+                let v = pixel[channel] as f32 / 255.0;
+                *value += if srgb && channel < 3 {
+                    srgb_to_linear(v)
+                } else {
+                    v
+                };
+            }
+        }
+        image::Rgba(std::array::from_fn(|channel| {
+            let v = sum[channel] / 4.0;
+            let v = if srgb && channel < 3 {
+                linear_to_srgb(v)
+            } else {
+                v
+            };
+            (v * 255.0).round() as u8
+        }))
+    })
+}
+
+/// Returns `image` followed by all its downsampled levels down to 1x1.
+pub fn mip_chain(image: RgbaImage, srgb: bool) -> Vec<RgbaImage> {
+    let mut mips = vec![image];
+    loop {
+        let last = mips.last().unwrap();
+        if last.width() == 1 && last.height() == 1 {
+            break mips;
+        }
+        let next = downsample(last, srgb);
+        mips.push(next);
+    }
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
 }
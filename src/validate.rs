@@ -0,0 +1,121 @@
+//! Checks every asset the game can find, for `tutorial12-camera validate`. Each file is
+//! loaded the way the game would load it, and all problems are collected so one run
+//! lists every broken file instead of stopping at the first.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufReader, Cursor};
+
+use crate::{
+    assets::TextureCache,
+    renderer::Renderer,
+    resources::{self, AssetSources},
+    shaders::{self, Shader},
+};
+
+/// A file that failed to load.
+#[derive(Debug)]
+pub struct Problem {
+    pub file: String,
+    pub error: anyhow::Error,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    // Files of a type the game loads, all others are skipped
+    pub checked: Vec<String>,
+    pub problems: Vec<Problem>,
+}
+
+/// Loads every model, material library, image, font and shader where the game finds its
+/// assets, see [`resources::asset_sources`]. Models are uploaded with `renderer`.
+pub async fn validate_assets(renderer: &Renderer) -> Result<Report> {
+    let sources = resources::asset_sources();
+    let textures = TextureCache::default();
+    let mut report = Report::default();
+    for file in sources.file_names()? {
+        let extension = file.rsplit_once('.').map_or("", |(_, extension)| extension);
+        let result = match extension {
+            "obj" | "gltf" | "glb" => resources::load_model(
+                &file,
+                &renderer.device,
+                &renderer.queue,
+                &renderer.texture_bind_group_layout,
+                &textures,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Into::into),
+            "mtl" => check_material_library(&sources, &file),
+            "png" => sources.read(&file).and_then(|data| {
+                image::load_from_memory(&data)?;
+                Ok(())
+            }),
+            "ttf" | "otf" => sources.read(&file).and_then(|data| {
+                fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+                    .map_err(|e| anyhow!("Could not parse font: {}", e))?;
+                Ok(())
+            }),
+            "wgsl" => check_shader(&sources, &file),
+            _ => continue,
+        };
+        if let Err(error) = result {
+            report.problems.push(Problem {
+                file: file.clone(),
+                error,
+            });
+        }
+        report.checked.push(file);
+    }
+    Ok(report)
+}
+
+/// Parses an MTL file and makes sure the textures it names exist.
+fn check_material_library(sources: &AssetSources, file: &str) -> Result<()> {
+    let text = String::from_utf8(sources.read(file)?)
+        .with_context(|| format!("{} is not UTF-8 text", file))?;
+    let (materials, _) = tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(text)))?;
+    for material in materials {
+        for texture in [&material.diffuse_texture, &material.normal_texture] {
+            if !texture.is_empty() && !sources.contains(texture) {
+                bail!("{} refers to {}, which does not exist", file, texture);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shaders replace the one of the renderer with the same file name in `shaders/`.
+fn check_shader(sources: &AssetSources, file: &str) -> Result<()> {
+    let shader = Shader::ALL
+        .into_iter()
+        .find(|shader| file == format!("shaders/{}", shader.file_name()))
+        .with_context(|| format!("{} does not replace any shader of the renderer", file))?;
+    let source = String::from_utf8(sources.read(file)?)
+        .with_context(|| format!("{} is not UTF-8 text", file))?;
+    shaders::validate(shader, &source)
+}
+
+/// Prints every problem of the assets, for the `validate` command. Returns whether there
+/// were none.
+pub async fn run() -> bool {
+    let report = match Renderer::new_headless(1, 1).await {
+        Ok(renderer) => validate_assets(&renderer).await,
+        Err(e) => Err(e),
+    };
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Could not validate the assets: {:#}", e);
+            return false;
+        }
+    };
+    for problem in &report.problems {
+        println!("{}: {:#}", problem.file, problem.error);
+    }
+    println!(
+        "Checked {} assets, {} with problems",
+        report.checked.len(),
+        report.problems.len()
+    );
+    report.problems.is_empty()
+}
@@ -0,0 +1,310 @@
+//! Vertex attributes of the buffers every vertex shader reads. build.rs includes this
+//! file as well and checks every attribute against the WGSL vertex inputs, so it may only
+//! depend on `wgpu_types`.
+
+use std::mem::size_of;
+use wgpu_types::{BufferAddress, VertexAttribute, VertexFormat};
+
+/// `ModelVertex`, one per mesh vertex
+pub const MODEL_VERTEX_ATTRIBUTES: [VertexAttribute; 5] = [
+    // Position
+    VertexAttribute {
+        offset: 0,
+        shader_location: 0,
+        format: VertexFormat::Float32x3,
+    },
+    // Texture coordinates
+    VertexAttribute {
+        offset: size_of::<[f32; 3]>() as BufferAddress,
+        shader_location: 1,
+        format: VertexFormat::Float32x2,
+    },
+    // Normal
+    VertexAttribute {
+        offset: size_of::<[f32; 5]>() as BufferAddress,
+        shader_location: 2,
+        format: VertexFormat::Float32x3,
+    },
+    // Tangent
+    VertexAttribute {
+        offset: size_of::<[f32; 8]>() as BufferAddress,
+        shader_location: 3,
+        format: VertexFormat::Float32x3,
+    },
+    // Bitangent
+    VertexAttribute {
+        offset: size_of::<[f32; 11]>() as BufferAddress,
+        shader_location: 4,
+        format: VertexFormat::Float32x3,
+    },
+];
+
+/// `InstanceRaw`, one per block. Starts at location 5 so it does not clash with the
+/// vertex attributes.
+pub const INSTANCE_ATTRIBUTES: [VertexAttribute; 8] = [
+    // A mat4 takes up 4 vertex slots as it is technically 4 vec4s
+    VertexAttribute {
+        offset: 0,
+        shader_location: 5,
+        format: VertexFormat::Float32x4,
+    },
+    VertexAttribute {
+        offset: size_of::<[f32; 4]>() as BufferAddress,
+        shader_location: 6,
+        format: VertexFormat::Float32x4,
+    },
+    VertexAttribute {
+        offset: size_of::<[f32; 8]>() as BufferAddress,
+        shader_location: 7,
+        format: VertexFormat::Float32x4,
+    },
+    VertexAttribute {
+        offset: size_of::<[f32; 12]>() as BufferAddress,
+        shader_location: 8,
+        format: VertexFormat::Float32x4,
+    },
+    // Normal matrix
+    VertexAttribute {
+        offset: size_of::<[f32; 16]>() as BufferAddress,
+        shader_location: 9,
+        format: VertexFormat::Float32x3,
+    },
+    VertexAttribute {
+        offset: size_of::<[f32; 19]>() as BufferAddress,
+        shader_location: 10,
+        format: VertexFormat::Float32x3,
+    },
+    VertexAttribute {
+        offset: size_of::<[f32; 22]>() as BufferAddress,
+        shader_location: 11,
+        format: VertexFormat::Float32x3,
+    },
+    // Atlas rectangle of the block's texture
+    VertexAttribute {
+        offset: size_of::<[f32; 25]>() as BufferAddress,
+        shader_location: 12,
+        format: VertexFormat::Float32x4,
+    },
+];
+
+/// `SkinVertex`, one per vertex of a skinned mesh. Follows the instance attributes.
+pub const SKIN_VERTEX_ATTRIBUTES: [VertexAttribute; 2] = [
+    // Joints
+    VertexAttribute {
+        offset: 0,
+        shader_location: 13,
+        format: VertexFormat::Uint32x4,
+    },
+    // Weights
+    VertexAttribute {
+        offset: size_of::<[u32; 4]>() as BufferAddress,
+        shader_location: 14,
+        format: VertexFormat::Float32x4,
+    },
+];
+
+/// `ParticleRaw`, one per particle. The corners of the quad come from the vertex index.
+pub const PARTICLE_ATTRIBUTES: [VertexAttribute; 5] = [
+    // Centre
+    VertexAttribute {
+        offset: 0,
+        shader_location: 0,
+        format: VertexFormat::Float32x3,
+    },
+    // Edge length
+    VertexAttribute {
+        offset: size_of::<[f32; 3]>() as BufferAddress,
+        shader_location: 1,
+        format: VertexFormat::Float32,
+    },
+    // Atlas rectangle, empty for untextured particles
+    VertexAttribute {
+        offset: size_of::<[f32; 4]>() as BufferAddress,
+        shader_location: 2,
+        format: VertexFormat::Float32x4,
+    },
+    // Colour
+    VertexAttribute {
+        offset: size_of::<[f32; 8]>() as BufferAddress,
+        shader_location: 3,
+        format: VertexFormat::Float32x4,
+    },
+    // Glow
+    VertexAttribute {
+        offset: size_of::<[f32; 12]>() as BufferAddress,
+        shader_location: 4,
+        format: VertexFormat::Float32,
+    },
+];
+
+/// Corners of the outline box, used by both its lines and its faces
+pub const OUTLINE_VERTEX_ATTRIBUTES: [VertexAttribute; 1] = [VertexAttribute {
+    offset: 0,
+    shader_location: 0,
+    format: VertexFormat::Float32x3,
+}];
+
+/// `SpriteVertex`, one per corner of an overlay quad
+pub const SPRITE_VERTEX_ATTRIBUTES: [VertexAttribute; 3] = [
+    // Position in pixels
+    VertexAttribute {
+        offset: 0,
+        shader_location: 0,
+        format: VertexFormat::Float32x2,
+    },
+    // Texture coordinates
+    VertexAttribute {
+        offset: size_of::<[f32; 2]>() as BufferAddress,
+        shader_location: 1,
+        format: VertexFormat::Float32x2,
+    },
+    // Colour
+    VertexAttribute {
+        offset: size_of::<[f32; 4]>() as BufferAddress,
+        shader_location: 2,
+        format: VertexFormat::Float32x4,
+    },
+];
+
+/// Vertex shaders and the attributes of the buffers bound when they run, in buffer order.
+/// Every vertex entry point has to be listed, validation rejects the ones that are not.
+pub const VERTEX_SHADERS: [(&str, &str, &[&[VertexAttribute]]); 10] = [
+    (
+        "shader.wgsl",
+        "vs_main",
+        &[&MODEL_VERTEX_ATTRIBUTES, &INSTANCE_ATTRIBUTES],
+    ),
+    (
+        "shader.wgsl",
+        "vs_skinned",
+        &[
+            &MODEL_VERTEX_ATTRIBUTES,
+            &INSTANCE_ATTRIBUTES,
+            &SKIN_VERTEX_ATTRIBUTES,
+        ],
+    ),
+    ("light.wgsl", "vs_main", &[&MODEL_VERTEX_ATTRIBUTES]),
+    (
+        "shadow.wgsl",
+        "vs_main",
+        &[&MODEL_VERTEX_ATTRIBUTES, &INSTANCE_ATTRIBUTES],
+    ),
+    (
+        "shadow.wgsl",
+        "vs_skinned",
+        &[
+            &MODEL_VERTEX_ATTRIBUTES,
+            &INSTANCE_ATTRIBUTES,
+            &SKIN_VERTEX_ATTRIBUTES,
+        ],
+    ),
+    ("particle.wgsl", "vs_main", &[&PARTICLE_ATTRIBUTES]),
+    // The sky is a full screen triangle made from the vertex index
+    ("sky.wgsl", "vs_main", &[]),
+    ("outline.wgsl", "vs_line", &[&OUTLINE_VERTEX_ATTRIBUTES]),
+    ("outline.wgsl", "vs_face", &[&OUTLINE_VERTEX_ATTRIBUTES]),
+    ("overlay.wgsl", "vs_main", &[&SPRITE_VERTEX_ATTRIBUTES]),
+];
+
+/// Checks that every `@location` input of `entry_point` is provided by exactly one of the
+/// attributes in `buffers` with a matching scalar kind and component count.
+pub fn check_vertex_inputs(
+    module: &naga::Module,
+    entry_point: &str,
+    buffers: &[&[VertexAttribute]],
+) -> Result<(), String> {
+    let function = &module
+        .entry_points
+        .iter()
+        .find(|e| e.stage == naga::ShaderStage::Vertex && e.name == entry_point)
+        .ok_or_else(|| format!("there is no vertex entry point `{}`", entry_point))?
+        .function;
+
+    // Inputs are either arguments of their own or members of a struct argument
+    let mut inputs = Vec::new();
+    for argument in &function.arguments {
+        match (&argument.binding, &module.types[argument.ty].inner) {
+            (Some(naga::Binding::Location { location, .. }), _) => {
+                inputs.push((*location, argument.name.clone(), argument.ty))
+            }
+            (None, naga::TypeInner::Struct { members, .. }) => {
+                for member in members {
+                    if let Some(naga::Binding::Location { location, .. }) = member.binding {
+                        inputs.push((location, member.name.clone(), member.ty));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let attributes: Vec<&VertexAttribute> = buffers.iter().flat_map(|b| b.iter()).collect();
+    for (location, name, ty) in inputs {
+        let name = name.unwrap_or_else(|| format!("location {}", location));
+        let provided: Vec<_> = attributes
+            .iter()
+            .filter(|a| a.shader_location == location)
+            .collect();
+        let attribute = match provided[..] {
+            [attribute] => attribute,
+            [] => {
+                return Err(format!(
+                    "`{}` reads @location({}) but no vertex attribute provides it",
+                    name, location
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "@location({}) of `{}` is provided by {} vertex attributes",
+                    location,
+                    name,
+                    provided.len()
+                ))
+            }
+        };
+        let expected = match module.types[ty].inner {
+            naga::TypeInner::Scalar { kind, .. } => (kind, 1),
+            naga::TypeInner::Vector { size, kind, .. } => (kind, size as u32),
+            ref other => return Err(format!("`{}` has unsupported type {:?}", name, other)),
+        };
+        if format_components(attribute.format) != expected {
+            return Err(format!(
+                "`{}` at @location({}) is {:?} x{} in the shader but {:?} in Rust",
+                name, location, expected.0, expected.1, attribute.format
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The scalar kind and component count a vertex format shows up as in a shader.
+/// Normalized formats are read as floats.
+fn format_components(format: VertexFormat) -> (naga::ScalarKind, u32) {
+    use naga::ScalarKind::{Float, Sint, Uint};
+    match format {
+        VertexFormat::Uint32 => (Uint, 1),
+        VertexFormat::Uint8x2 | VertexFormat::Uint16x2 | VertexFormat::Uint32x2 => (Uint, 2),
+        VertexFormat::Uint32x3 => (Uint, 3),
+        VertexFormat::Uint8x4 | VertexFormat::Uint16x4 | VertexFormat::Uint32x4 => (Uint, 4),
+        VertexFormat::Sint32 => (Sint, 1),
+        VertexFormat::Sint8x2 | VertexFormat::Sint16x2 | VertexFormat::Sint32x2 => (Sint, 2),
+        VertexFormat::Sint32x3 => (Sint, 3),
+        VertexFormat::Sint8x4 | VertexFormat::Sint16x4 | VertexFormat::Sint32x4 => (Sint, 4),
+        VertexFormat::Float32 | VertexFormat::Float64 => (Float, 1),
+        VertexFormat::Unorm8x2
+        | VertexFormat::Snorm8x2
+        | VertexFormat::Unorm16x2
+        | VertexFormat::Snorm16x2
+        | VertexFormat::Float16x2
+        | VertexFormat::Float32x2
+        | VertexFormat::Float64x2 => (Float, 2),
+        VertexFormat::Float32x3 | VertexFormat::Float64x3 => (Float, 3),
+        VertexFormat::Unorm8x4
+        | VertexFormat::Snorm8x4
+        | VertexFormat::Unorm16x4
+        | VertexFormat::Snorm16x4
+        | VertexFormat::Float16x4
+        | VertexFormat::Float32x4
+        | VertexFormat::Float64x4 => (Float, 4),
+    }
+}
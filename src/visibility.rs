@@ -0,0 +1,138 @@
+//! Occlusion culling for caves and other enclosed spaces. The world is split into cubic
+//! sections as large as a chunk. For every section the pairs of its faces that are
+//! connected through blocks which are not opaque are worked out whenever it changes, and
+//! every frame a flood fill from the section with the camera walks through these
+//! connections. Sections it never reaches cannot be seen and are skipped while drawing.
+
+use cgmath::Vector3;
+use std::collections::{HashSet, VecDeque};
+
+use crate::{block::BlockFace, world::CHUNK_SIZE};
+
+/// Blocks along every edge of a section
+pub const SECTION_BLOCKS: i32 = CHUNK_SIZE / 2;
+
+fn face_index(face: BlockFace) -> usize {
+    BlockFace::ALL.iter().position(|f| *f == face).unwrap()
+}
+
+/// Which faces of a section can be seen from which other faces.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FaceConnections(u64);
+
+impl FaceConnections {
+    /// Every face sees every other one, true for a section without opaque blocks.
+    pub const ALL: FaceConnections = FaceConnections((1 << 36) - 1);
+
+    pub fn connects(&self, a: BlockFace, b: BlockFace) -> bool {
+        self.0 & (1 << (face_index(a) * 6 + face_index(b))) != 0
+    }
+
+    /// Connects every face in `faces` with every other face in it.
+    fn connect_all(&mut self, faces: &[BlockFace]) {
+        for a in faces {
+            for b in faces {
+                self.0 |= 1 << (face_index(*a) * 6 + face_index(*b));
+            }
+        }
+    }
+}
+
+/// Works out the face connections of a section from the positions of its opaque blocks,
+/// given in blocks from its lowest corner (0 to `SECTION_BLOCKS - 1` along every axis).
+pub fn face_connections(opaque: impl IntoIterator<Item = Vector3<i32>>) -> FaceConnections {
+    const SIZE: i32 = SECTION_BLOCKS;
+    let index = |cell: Vector3<i32>| (cell.x + SIZE * (cell.y + SIZE * cell.z)) as usize;
+    let inside = |cell: Vector3<i32>| {
+        (0..SIZE).contains(&cell.x) && (0..SIZE).contains(&cell.y) && (0..SIZE).contains(&cell.z)
+    };
+
+    // Opaque cells are marked as visited up front so the flood fill never enters them
+    let mut visited = vec![false; (SIZE * SIZE * SIZE) as usize];
+    let mut any_opaque = false;
+    for cell in opaque {
+        if inside(cell) {
+            visited[index(cell)] = true;
+            any_opaque = true;
+        }
+    }
+    if !any_opaque {
+        return FaceConnections::ALL;
+    }
+
+    let mut connections = FaceConnections::default();
+    let mut queue = VecDeque::new();
+    for x in 0..SIZE {
+        for y in 0..SIZE {
+            for z in 0..SIZE {
+                let start = Vector3::new(x, y, z);
+                if visited[index(start)] {
+                    continue;
+                }
+                // Every open area touching several faces lets them see each other
+                visited[index(start)] = true;
+                queue.push_back(start);
+                let mut faces = Vec::new();
+                while let Some(cell) = queue.pop_front() {
+                    for face in BlockFace::ALL {
+                        let next = cell + face.normal();
+                        if !inside(next) {
+                            if !faces.contains(&face) {
+                                faces.push(face);
+                            }
+                        } else if !visited[index(next)] {
+                            visited[index(next)] = true;
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                connections.connect_all(&faces);
+            }
+        }
+    }
+    connections
+}
+
+/// Sections that can possibly be seen from `start`. The flood fill only leaves a section
+/// through a face that is connected to the one it came in through, and never turns back
+/// towards a direction it already went, so it cannot bend around behind a wall.
+/// It stays within the inclusive bounds `min..=max`, which are widened to hold `start`.
+pub fn visible_sections(
+    start: Vector3<i32>,
+    (min, max): (Vector3<i32>, Vector3<i32>),
+    connections: impl Fn(Vector3<i32>) -> FaceConnections,
+) -> HashSet<Vector3<i32>> {
+    let min = min.zip(start, i32::min);
+    let max = max.zip(start, i32::max);
+    let inside = |s: Vector3<i32>| {
+        (min.x..=max.x).contains(&s.x)
+            && (min.y..=max.y).contains(&s.y)
+            && (min.z..=max.z).contains(&s.z)
+    };
+
+    let mut visible = HashSet::from([start]);
+    // Section, the face it was entered through and the directions taken to get there
+    let mut queue = VecDeque::from([(start, None::<BlockFace>, 0u8)]);
+    while let Some((section, entered_through, directions)) = queue.pop_front() {
+        let section_connections = connections(section);
+        for face in BlockFace::ALL {
+            if directions & (1 << face_index(face.opposite())) != 0 {
+                continue;
+            }
+            if let Some(entered_through) = entered_through {
+                if !section_connections.connects(entered_through, face) {
+                    continue;
+                }
+            }
+            let next = section + face.normal();
+            if inside(next) && visible.insert(next) {
+                queue.push_back((
+                    next,
+                    Some(face.opposite()),
+                    directions | 1 << face_index(face),
+                ));
+            }
+        }
+    }
+    visible
+}
@@ -1,42 +1,117 @@
-use crate::Instance;
-use cgmath::{Quaternion, Vector3, Zero};
-use std::collections::HashMap;
+use crate::{
+    animation::TextureAnimations,
+    atlas::{Atlas, AtlasBuilder},
+    block::{Block, BlockFace, BlockKind, BlockLayer},
+    entity::{Entities, ModelId, ModelRegistry},
+    lod::{LodBlock, LOD_LEVELS},
+    particles::ParticleSystem,
+    visibility::FaceConnections,
+};
+use anyhow::Context;
+use cgmath::{EuclideanSpace, Point3, Vector2, Vector3};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+/// Edge length of a chunk in world units, 16 blocks of size 2
+pub const CHUNK_SIZE: i32 = 32;
+
+// Any seed works, a fixed one makes every run and screenshot look the same
+const PARTICLE_SEED: u64 = 0x5EED;
+
+/// Horizontal coordinates of the chunk containing `coords`.
+pub fn chunk_of(coords: Vector3<i32>) -> Vector2<i32> {
+    Vector2::new(
+        coords.x.div_euclid(CHUNK_SIZE),
+        coords.z.div_euclid(CHUNK_SIZE),
+    )
+}
+
+/// The blocks in one chunk together with their downsampled copies for drawing it far away.
+#[derive(Default)]
+pub struct Chunk {
+    blocks: HashSet<Vector3<i32>>,
+    lods: [Vec<LodBlock>; LOD_LEVELS],
+}
+
+impl Chunk {
+    /// Coordinates of every block in the chunk.
+    pub fn blocks(&self) -> impl Iterator<Item = &Vector3<i32>> {
+        self.blocks.iter()
+    }
+
+    /// The boxes the chunk is drawn with at `level`, starting at 1 for the first
+    /// downsampled level.
+    pub fn lod(&self, level: usize) -> &[LodBlock] {
+        &self.lods[level - 1]
+    }
+}
+
+/// Coordinates of the cubic section containing `coords`, a chunk is a column of them.
+pub fn section_of(coords: Vector3<i32>) -> Vector3<i32> {
+    coords.map(|v| v.div_euclid(CHUNK_SIZE))
+}
+
+/// Coordinates of the block cell a point in world units lies in.
+pub fn block_at(position: Point3<f32>) -> Vector3<i32> {
+    // Blocks are two units wide and centred on even coordinates
+    Vector3::new(position.x, position.y, position.z).map(|v| ((v + 1.0) / 2.0).floor() as i32 * 2)
+}
 
 pub struct World {
-    blocks: HashMap<Vector3<i32>, Instance>,
-    pub obj_model: crate::model::Model,
+    blocks: HashMap<Vector3<i32>, Block>,
+    chunks: HashMap<Vector2<i32>, Chunk>,
+    // Only sections with opaque blocks in them, all others connect every face
+    sections: HashMap<Vector3<i32>, FaceConnections>,
+    // The cube every block is drawn with, the world holds a reference to it
+    pub obj_model: ModelId,
+    atlas: Atlas,
+    pub atlas_material: crate::model::Material,
+    animations: TextureAnimations,
+    // Broken blocks and other effects, drawn together with the blocks
+    pub particles: ParticleSystem,
+    // Models for the entities, loaded on demand
+    pub models: ModelRegistry,
+    pub entities: Entities,
 }
 
 impl World {
-    pub async fn new(camera: &crate::camera::Camera) -> Self {
-        let debug_material = {
-            let diffuse_bytes = include_bytes!("../res/cobble-diffuse.png");
-            let diffuse_texture = crate::texture::Texture::from_bytes(
-                &camera.device,
-                &camera.queue,
-                diffuse_bytes,
-                "res/alt-diffuse.png",
+    pub async fn new(renderer: &crate::renderer::Renderer) -> Self {
+        let mut models = ModelRegistry::default();
+        let obj_model = models.load("cube.obj", renderer).await.unwrap();
+
+        let (atlas, normal_atlas, animations) = World::build_atlas().await.unwrap();
+        if let Ok(path) = std::env::var("ATLAS_DEBUG_PNG") {
+            atlas.save_debug_png(path).unwrap();
+        }
+        let atlas_material = {
+            let atlas_texture = crate::texture::Texture::from_mip_chain(
+                &renderer.device,
+                &renderer.queue,
+                &atlas.mips,
+                Some("block_atlas"),
                 false,
             )
             .unwrap();
+            let normal_atlas_texture = crate::texture::Texture::from_mip_chain(
+                &renderer.device,
+                &renderer.queue,
+                &normal_atlas.mips,
+                Some("block_normal_atlas"),
+                true,
+            )
+            .unwrap();
             crate::model::Material::new(
-                &camera.device,
-                "alt-material",
-                diffuse_texture,
-                &camera.texture_bind_group_layout,
+                &renderer.device,
+                "atlas-material",
+                atlas_texture,
+                normal_atlas_texture,
+                &renderer.texture_bind_group_layout,
             )
         };
 
-        let obj_model = crate::resources::load_model(
-            "cube.obj",
-            &camera.device,
-            &camera.queue,
-            &camera.texture_bind_group_layout,
-        )
-        .await
-        .unwrap();
-
-        let mut blocks: HashMap<Vector3<i32>, Instance> = HashMap::new();
+        let mut blocks: HashMap<Vector3<i32>, Block> = HashMap::new();
         for x in 0..100 {
             for z in 0..100 {
                 let position = Vector3 {
@@ -49,30 +124,261 @@ impl World {
                     y: 0.0,
                     z: z as f32 * 2.0,
                 };
-                let instance = Instance {
+                let block = Block {
                     position: position_exact,
-                    rotation: Quaternion::zero(),
+                    kind: BlockKind::Dirt,
                 };
-                blocks.insert(position, instance);
+                blocks.insert(position, block);
             }
         }
 
-        Self { blocks, obj_model }
+        let mut world = Self {
+            blocks,
+            chunks: HashMap::new(),
+            sections: HashMap::new(),
+            obj_model,
+            atlas,
+            atlas_material,
+            animations,
+            particles: ParticleSystem::new(PARTICLE_SEED),
+            models,
+            entities: Entities::default(),
+        };
+        let coords: Vec<_> = world.blocks.keys().copied().collect();
+        for coords in coords {
+            world
+                .chunks
+                .entry(chunk_of(coords))
+                .or_default()
+                .blocks
+                .insert(coords);
+        }
+        let chunks: Vec<_> = world.chunks.keys().copied().collect();
+        for chunk in chunks {
+            world.update_lods(chunk);
+        }
+        let sections: HashSet<_> = world.blocks.keys().map(|c| section_of(*c)).collect();
+        for section in sections {
+            world.update_section(section);
+        }
+        world
+    }
+
+    /// Packs the face textures of every block kind into one atlas and their normal maps
+    /// into a second one with the same layout. Both atlases are keyed by the diffuse texture.
+    /// Animated textures are packed with their first frame and returned with all of them.
+    async fn build_atlas() -> anyhow::Result<(Atlas, Atlas, TextureAnimations)> {
+        let mut names = BlockKind::ALL
+            .iter()
+            .flat_map(|kind| {
+                BlockFace::ALL.iter().map(|face| {
+                    (
+                        kind.texture(*face),
+                        kind.normal_texture(*face),
+                        kind.animation(*face),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable_by_key(|(name, normal_name, _)| (*name, *normal_name));
+        names.dedup_by_key(|(name, _, _)| *name);
+
+        let mut builder = AtlasBuilder::new(4);
+        let mut normal_builder = AtlasBuilder::new(4);
+        normal_builder.is_normal_map(true);
+        let mut animations = TextureAnimations::default();
+        for (name, normal_name, animation) in names {
+            let bytes = crate::resources::load_binary(name).await?;
+            let mut diffuse = image::load_from_memory(&bytes)?.to_rgba8();
+            if let Some(animation) = animation {
+                let frames = animation
+                    .split_strip(&diffuse)
+                    .with_context(|| format!("Animated texture {}", name))?;
+                diffuse = frames[0].clone();
+                animations.add(name, animation, frames)?;
+            }
+            let normal = match normal_name {
+                Some(normal_name) => {
+                    let bytes = crate::resources::load_binary(normal_name).await?;
+                    image::load_from_memory(&bytes)?.to_rgba8()
+                }
+                None => image::RgbaImage::from_pixel(
+                    diffuse.width(),
+                    diffuse.height(),
+                    image::Rgba(crate::texture::FLAT_NORMAL),
+                ),
+            };
+            anyhow::ensure!(
+                normal.dimensions() == diffuse.dimensions(),
+                "Normal map of {} has a different size than the texture",
+                name
+            );
+            builder.add(name, diffuse);
+            normal_builder.add(name, normal);
+        }
+        Ok((builder.build()?, normal_builder.build()?, animations))
     }
 
-    pub fn blocks(&self) -> &HashMap<Vector3<i32>, Instance> {
+    pub fn blocks(&self) -> &HashMap<Vector3<i32>, Block> {
         &self.blocks
     }
 
-    pub fn destroy(&mut self, coords: &Vector3<i32>) {
-        self.blocks.remove(coords);
+    /// The model of `obj_model`.
+    pub fn block_model(&self) -> &crate::model::Model {
+        self.models
+            .get(self.obj_model)
+            .expect("the block model is loaded with the world and never released")
     }
 
-    pub fn place(&mut self, coords: Vector3<i32>) {
-        let instance = Instance {
+    pub fn atlas(&self) -> &Atlas {
+        &self.atlas
+    }
+
+    /// Chunks with at least one block in them, by their horizontal coordinates.
+    pub fn chunks(&self) -> &HashMap<Vector2<i32>, Chunk> {
+        &self.chunks
+    }
+
+    /// Number of chunks with at least one block in them.
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Removes the block at `coords` and returns it, if there was one.
+    pub fn destroy(&mut self, coords: &Vector3<i32>) -> Option<Block> {
+        let block = self.blocks.remove(coords)?;
+        let chunk = chunk_of(*coords);
+        if let Some(blocks) = self.chunks.get_mut(&chunk).map(|chunk| &mut chunk.blocks) {
+            blocks.remove(coords);
+            if blocks.is_empty() {
+                self.chunks.remove(&chunk);
+            }
+        }
+        self.update_lods(chunk);
+        self.update_section(section_of(*coords));
+        Some(block)
+    }
+
+    pub fn place(&mut self, coords: Vector3<i32>, kind: BlockKind) {
+        let block = Block {
             position: coords.map(|v| v as f32),
-            rotation: Quaternion::zero(),
+            kind,
         };
-        self.blocks.insert(coords, instance);
+        self.blocks.insert(coords, block);
+        let chunk = chunk_of(coords);
+        self.chunks.entry(chunk).or_default().blocks.insert(coords);
+        self.update_lods(chunk);
+        self.update_section(section_of(coords));
+    }
+
+    /// Takes in the models that finished loading and moves the particles, entity
+    /// animations and texture animations on by `dt`. Particles come to rest on top of
+    /// blocks, animated textures are written into the atlas when their frame changes.
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: Duration) {
+        let blocks = &self.blocks;
+        self.particles.update(dt, |position| {
+            blocks.contains_key(&block_at(Point3::from_vec(position)))
+        });
+
+        self.models.update();
+        self.entities.update(dt, &self.models);
+
+        self.animations.advance(dt);
+        for (name, image) in self.animations.take_changed() {
+            match self.atlas.patch_tile(name, &image) {
+                Ok(patches) => {
+                    for patch in patches {
+                        self.atlas_material.diffuse_texture.write_region(
+                            queue,
+                            patch.level,
+                            patch.x,
+                            patch.y,
+                            &patch.image,
+                        );
+                    }
+                }
+                Err(e) => log::warn!("Could not animate {}: {:#}", name, e),
+            }
+        }
+    }
+
+    pub fn animations(&self) -> &TextureAnimations {
+        &self.animations
+    }
+
+    /// Which faces of `section` are connected through blocks that are not opaque.
+    pub fn face_connections(&self, section: Vector3<i32>) -> FaceConnections {
+        self.sections
+            .get(&section)
+            .copied()
+            .unwrap_or(FaceConnections::ALL)
+    }
+
+    /// Sections that could be seen from `position`, at most `radius` sections away
+    /// horizontally. `None` if nothing can be hidden because there are no opaque blocks.
+    pub fn visible_sections(
+        &self,
+        position: Point3<f32>,
+        radius: i32,
+    ) -> Option<HashSet<Vector3<i32>>> {
+        let mut sections = self.sections.keys();
+        let first = *sections.next()?;
+        // One section of empty space around the opaque ones is enough to go around them
+        let (min, max) = sections.fold((first, first), |(min, max), s| {
+            (min.zip(*s, i32::min), max.zip(*s, i32::max))
+        });
+        let start = section_of(block_at(position));
+        let min = Vector3::new(
+            (min.x - 1).max(start.x - radius),
+            min.y - 1,
+            (min.z - 1).max(start.z - radius),
+        );
+        let max = Vector3::new(
+            (max.x + 1).min(start.x + radius),
+            max.y + 1,
+            (max.z + 1).min(start.z + radius),
+        );
+        Some(crate::visibility::visible_sections(
+            start,
+            (min, max),
+            |section| self.face_connections(section),
+        ))
+    }
+
+    /// Works out the face connections of `section` again after its blocks changed.
+    fn update_section(&mut self, section: Vector3<i32>) {
+        let origin = section * CHUNK_SIZE;
+        let opaque: Vec<_> = self
+            .chunks
+            .get(&Vector2::new(section.x, section.z))
+            .into_iter()
+            .flat_map(|chunk| chunk.blocks())
+            .filter(|coords| section_of(**coords) == section)
+            .filter(|coords| self.blocks[*coords].kind.layer() == BlockLayer::Opaque)
+            .map(|coords| (coords - origin).map(|v| v / 2))
+            .collect();
+        if opaque.is_empty() {
+            self.sections.remove(&section);
+        } else {
+            let connections = crate::visibility::face_connections(opaque);
+            self.sections.insert(section, connections);
+        }
+    }
+
+    /// Rebuilds the downsampled levels of `chunk` after its blocks changed.
+    fn update_lods(&mut self, chunk: Vector2<i32>) {
+        let Some(chunk) = self.chunks.get_mut(&chunk) else {
+            return;
+        };
+        let blocks = &self.blocks;
+        for (level, lod) in chunk.lods.iter_mut().enumerate() {
+            *lod = crate::lod::downsample(
+                chunk
+                    .blocks
+                    .iter()
+                    .map(|coords| (*coords, blocks[coords].kind)),
+                level + 1,
+            );
+        }
     }
 }
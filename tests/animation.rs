@@ -0,0 +1,131 @@
+//! Frame timing and strips are plain data, only the last test renders.
+
+mod common;
+
+use cgmath::{Deg, Point3, Rad, Vector3};
+use image::RgbaImage;
+use std::time::Duration;
+use tutorial12_camera::{
+    animation::{TextureAnimation, TextureAnimations},
+    block::{BlockFace, BlockKind},
+    offscreen::OffscreenTarget,
+    world::World,
+};
+
+fn animation(interpolate: bool) -> TextureAnimation {
+    TextureAnimation::new(4, 0.5, interpolate).unwrap()
+}
+
+/// A strip of solid frames with the red channel at 0, 40, 80 and so on.
+fn strip(frames: u32) -> RgbaImage {
+    RgbaImage::from_fn(2, 2 * frames, |_, y| {
+        image::Rgba([(y / 2 * 40) as u8, 0, 0, 255])
+    })
+}
+
+#[test]
+fn frames_follow_the_time_and_loop() {
+    let steps = animation(false);
+    assert_eq!(steps.duration(), 2.0);
+    assert_eq!(steps.frame_at(0.0), (0, 1, 0.0));
+    assert_eq!(steps.frame_at(0.75), (1, 2, 0.0));
+    assert_eq!(steps.frame_at(1.5), (3, 0, 0.0));
+    assert_eq!(steps.frame_at(2.25), (0, 1, 0.0));
+
+    let smooth = animation(true);
+    assert_eq!(smooth.frame_at(0.75), (1, 2, 0.5));
+    assert_eq!(smooth.frame_at(1.875), (3, 0, 0.75));
+}
+
+#[test]
+fn strips_split_into_frames() {
+    let frames = animation(false).split_strip(&strip(4)).unwrap();
+    assert_eq!(frames.len(), 4);
+    for (index, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.dimensions(), (2, 2));
+        assert_eq!(frame.get_pixel(1, 1)[0], index as u8 * 40);
+    }
+    assert!(animation(false).split_strip(&strip(3)).is_err());
+}
+
+#[test]
+fn animations_that_cannot_play_are_rejected() {
+    assert!(TextureAnimation::new(0, 0.5, false).is_err());
+    for frame_duration in [0.0, -0.5, f32::NAN, f32::INFINITY] {
+        assert!(TextureAnimation::new(4, frame_duration, true).is_err());
+    }
+    let single = TextureAnimation::new(1, 0.5, true).unwrap();
+    assert_eq!(single.frame_at(0.75), (0, 0, 0.5));
+
+    // A strip cut for another animation would be played past its end
+    let mut animations = TextureAnimations::default();
+    let frames = animation(true).split_strip(&strip(4)).unwrap();
+    let error = animations
+        .add(
+            "lava.png",
+            TextureAnimation::new(8, 0.5, true).unwrap(),
+            frames,
+        )
+        .unwrap_err();
+    assert!(error.to_string().contains("lava.png"));
+    let mut frames = animation(true).split_strip(&strip(4)).unwrap();
+    frames[3] = RgbaImage::new(1, 1);
+    assert!(animations.add("lava.png", animation(true), frames).is_err());
+    assert!(animations.frame("lava.png").is_none());
+}
+
+#[test]
+fn only_changed_frames_are_reported() {
+    let mut animations = TextureAnimations::default();
+    let frames = animation(true).split_strip(&strip(4)).unwrap();
+    animations.add("lava.png", animation(true), frames).unwrap();
+    assert!(animations.take_changed().is_empty());
+
+    // A quarter of the way into the fade from the first frame to the second
+    animations.advance(Duration::from_millis(125));
+    let changed = animations.take_changed();
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].0, "lava.png");
+    assert_eq!(changed[0].1.get_pixel(0, 0)[0], 10);
+    assert!(animations.take_changed().is_empty());
+
+    animations.advance(Duration::from_millis(1875));
+    assert_eq!(animations.frame("lava.png"), Some((0, 1, 0.0)));
+    assert_eq!(animations.take_changed()[0].1.get_pixel(0, 0)[0], 0);
+    assert_eq!(animations.frame("dirt.png"), None);
+}
+
+#[tokio::test]
+async fn water_moves_in_the_atlas() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+    for x in 0..3 {
+        world.place(Vector3::new(x * 2, 2, 0), BlockKind::Water);
+    }
+    let water = BlockKind::Water.texture(BlockFace::Top);
+    let looped = BlockKind::Water
+        .animation(BlockFace::Top)
+        .unwrap()
+        .duration();
+
+    renderer.update(
+        &Point3::new(2.0, 5.0, 4.0),
+        Rad(-0.9),
+        Rad::from(Deg(-90.0)),
+    );
+    let render = |world: &World| {
+        renderer.render(world, &target.view);
+        target.read_image(&renderer).unwrap()
+    };
+    let first = render(&world);
+
+    world.update(&renderer.queue, Duration::from_secs_f32(looped / 4.0));
+    assert_eq!(world.animations().frame(water).unwrap().0, 2);
+    let later = render(&world);
+    assert_ne!(first, later);
+
+    // Back at the first frame once the animation has played through
+    world.update(&renderer.queue, Duration::from_secs_f32(looped * 3.0 / 4.0));
+    assert_eq!(render(&world), first);
+}
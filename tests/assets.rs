@@ -0,0 +1,138 @@
+//! The store is tried with strings, the registry with the cube and a copy of it under
+//! another name in a temporary resource pack, which shares the textures of the cube.
+
+mod common;
+
+use std::time::{Duration, Instant};
+use tutorial12_camera::{
+    assets::{Assets, LoadState},
+    resources::{self, AssetSources},
+    world::World,
+};
+
+#[test]
+fn loads_are_shared_until_the_last_release() {
+    let mut assets = Assets::default();
+    let first = assets.load_with("a", || Ok("contents of a".to_string()));
+    let again = assets.load_with("a", || panic!("a is loaded already"));
+    assert_eq!(first, again);
+    assert_eq!(assets.references(first), 2);
+    assert_eq!(assets.wait(first), LoadState::Loaded);
+    assert_eq!(assets.get(first).unwrap(), "contents of a");
+    assert_eq!(assets.handle("a"), Some(first));
+
+    assert!(!assets.release(first));
+    assert!(assets.release(first));
+    assert_eq!(assets.state(first), LoadState::Unknown);
+    assert!(assets.get(first).is_none());
+    assert!(assets.is_empty());
+    // Loading it again makes a new asset that the old handle does not reach
+    let reloaded = assets.insert("a", "new contents".to_string());
+    assert_ne!(reloaded, first);
+    assert!(assets.get(first).is_none());
+
+    let missing = assets.load_with("b", || anyhow::bail!("There is no b"));
+    assert_eq!(
+        assets.wait(missing),
+        LoadState::Failed("There is no b".to_string())
+    );
+    let broken = assets.load_with("c", || panic!("c is broken"));
+    assert!(matches!(assets.wait(broken), LoadState::Failed(_)));
+
+    // Nobody gets to see what finishes after its last release
+    let slow = assets.load_with("d", || {
+        std::thread::sleep(Duration::from_millis(50));
+        Ok("contents of d".to_string())
+    });
+    assert!(assets.release(slow));
+    std::thread::sleep(Duration::from_millis(100));
+    assets.update();
+    assert_eq!(assets.state(slow), LoadState::Unknown);
+    assert_eq!(assets.len(), 3);
+}
+
+#[test]
+fn background_loads_share_one_thread() {
+    let mut assets = Assets::default();
+    let handles = (0..4)
+        .map(|i| assets.load_with(&format!("thread {}", i), || Ok(std::thread::current().id())))
+        .collect::<Vec<_>>();
+    let threads = handles
+        .iter()
+        .map(|&handle| {
+            assert_eq!(assets.wait(handle), LoadState::Loaded);
+            *assets.get(handle).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert!(threads.iter().all(|&thread| thread == threads[0]));
+    assert_ne!(threads[0], std::thread::current().id());
+
+    // Async loads run there as well
+    let future = assets.load_async_with("future", || async { Ok(std::thread::current().id()) });
+    assert_eq!(assets.wait(future), LoadState::Loaded);
+    assert_eq!(*assets.get(future).unwrap(), threads[0]);
+}
+
+#[tokio::test]
+async fn models_load_in_the_background_and_free_their_textures() {
+    let pack = common::TempDir::new("assets");
+    std::fs::write(
+        pack.join("crate.obj"),
+        resources::embedded("cube.obj").unwrap(),
+    )
+    .unwrap();
+    resources::set_asset_sources(AssetSources {
+        root: None,
+        packs: vec![pack.to_path_buf()],
+    });
+
+    let renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    // The block cube with its texture and normal map
+    assert_eq!(world.models.len(), 1);
+    assert_eq!(world.models.textures(), 2);
+    assert_eq!(world.models.references(world.obj_model), 1);
+
+    let cube = world.models.load("cube.obj", &renderer).await.unwrap();
+    assert_eq!(cube, world.obj_model);
+    assert_eq!(world.models.references(cube), 2);
+    assert!(!world.models.release(cube));
+
+    let pyramid = world.models.load_in_background("pyramid.obj", &renderer);
+    let crate_model = world.models.load_in_background("crate.obj", &renderer);
+    let start = Instant::now();
+    while [pyramid, crate_model]
+        .iter()
+        .any(|id| world.models.state(*id) == LoadState::Loading)
+    {
+        assert!(start.elapsed() < Duration::from_secs(30));
+        std::thread::sleep(Duration::from_millis(10));
+        world.update(&renderer.queue, Duration::ZERO);
+    }
+    assert!(world.models.get(pyramid).is_some());
+    assert_eq!(world.models.get(crate_model).unwrap().meshes.len(), 1);
+    // The crate uses the textures of the cube, the pyramid two of its own
+    assert_eq!(world.models.textures(), 4);
+    assert_eq!(world.models.len(), 3);
+    assert_eq!(
+        world.models.load("pyramid.obj", &renderer).await.unwrap(),
+        pyramid
+    );
+
+    assert!(!world.models.release(pyramid));
+    assert!(world.models.release(pyramid));
+    assert!(world.models.release(crate_model));
+    assert!(world.models.get(pyramid).is_none());
+    assert_eq!(world.models.textures(), 2);
+    assert_eq!(world.models.len(), 1);
+
+    let missing = world.models.load_in_background("missing.obj", &renderer);
+    world
+        .models
+        .load("missing.obj", &renderer)
+        .await
+        .unwrap_err();
+    assert!(matches!(world.models.state(missing), LoadState::Failed(_)));
+
+    resources::set_asset_sources(AssetSources::default());
+}
@@ -0,0 +1,108 @@
+//! Packing tiles into the texture atlas, all on the CPU.
+
+use image::{Rgba, RgbaImage};
+use tutorial12_camera::atlas::{AtlasBuilder, MAX_ATLAS_SIZE};
+
+// Every texel of a different colour, so a copied texel shows where it came from
+fn tile(width: u32, height: u32, seed: u8) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, seed, 255]))
+}
+
+#[test]
+fn regions_do_not_overlap() {
+    let sizes = [
+        (16, 16),
+        (32, 16),
+        (8, 8),
+        (16, 32),
+        (8, 24),
+        (48, 8),
+        (16, 16),
+    ];
+    let mut builder = AtlasBuilder::new(2);
+    for (i, &(width, height)) in sizes.iter().enumerate() {
+        builder.add(&format!("tile{}", i), tile(width, height, i as u8));
+    }
+    let atlas = builder.build().unwrap();
+    let (width, height) = atlas.image().dimensions();
+    assert!(width.is_power_of_two() && height.is_power_of_two());
+
+    // Rectangles of the tiles with the requested padding around them
+    let padded = (0..sizes.len())
+        .map(|i| {
+            let region = atlas.region(&format!("tile{}", i)).unwrap();
+            assert_eq!((region.width, region.height), sizes[i]);
+            assert!(region.x >= 2 && region.y >= 2);
+            assert!(region.x + region.width + 2 <= width);
+            assert!(region.y + region.height + 2 <= height);
+            (
+                region.x - 2,
+                region.y - 2,
+                region.x + region.width + 2,
+                region.y + region.height + 2,
+            )
+        })
+        .collect::<Vec<_>>();
+    for (i, a) in padded.iter().enumerate() {
+        for b in &padded[i + 1..] {
+            let overlaps = a.0 < b.2 && b.0 < a.2 && a.1 < b.3 && b.1 < a.3;
+            assert!(!overlaps, "{:?} overlaps {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn padding_repeats_the_edge_texels() {
+    // Odd sizes keep the atlas at one mip level, so the padding is not rounded up
+    let mut builder = AtlasBuilder::new(3);
+    builder.add("a", tile(5, 3, 1)).add("b", tile(7, 9, 2));
+    let atlas = builder.build().unwrap();
+
+    for (name, seed) in [("a", 1), ("b", 2)] {
+        let region = *atlas.region(name).unwrap();
+        for dy in -3..region.height as i32 + 3 {
+            for dx in -3..region.width as i32 + 3 {
+                let texel = atlas
+                    .image()
+                    .get_pixel((region.x as i32 + dx) as u32, (region.y as i32 + dy) as u32);
+                let source_x = dx.clamp(0, region.width as i32 - 1) as u8;
+                let source_y = dy.clamp(0, region.height as i32 - 1) as u8;
+                assert_eq!(
+                    texel.0,
+                    [source_x, source_y, seed, 255],
+                    "{} at {} {}",
+                    name,
+                    dx,
+                    dy
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn duplicate_names_are_rejected() {
+    let mut builder = AtlasBuilder::new(1);
+    builder
+        .add("stone", tile(4, 4, 0))
+        .add("stone", tile(8, 8, 1));
+    let error = builder.build().err().unwrap();
+    assert!(error.to_string().contains("stone"));
+}
+
+#[test]
+fn oversized_tiles_fail() {
+    // Too wide on its own
+    let mut builder = AtlasBuilder::new(0);
+    builder.add("wide", tile(MAX_ATLAS_SIZE + 1, 1, 0));
+    assert!(builder.build().is_err());
+
+    // Fits without padding, but not with it
+    let mut builder = AtlasBuilder::new(1);
+    builder.add("wide", tile(MAX_ATLAS_SIZE, 1, 0));
+    assert!(builder.build().is_err());
+
+    let mut builder = AtlasBuilder::new(0);
+    builder.add("wide", tile(MAX_ATLAS_SIZE, 1, 0));
+    assert_eq!(builder.build().unwrap().image().width(), MAX_ATLAS_SIZE);
+}
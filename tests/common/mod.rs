@@ -0,0 +1,119 @@
+//! Setup shared by the test files, each of them includes it with `mod common;` and only
+//! uses part of it.
+#![allow(dead_code)]
+
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+use tutorial12_camera::renderer::Renderer;
+
+/// A renderer drawing into a `width`x`height` texture instead of a window.
+pub async fn headless_renderer(width: u32, height: u32) -> Renderer {
+    Renderer::new_headless(width, height)
+        .await
+        .expect("the test needs a wgpu adapter, a software one is enough")
+}
+
+/// An empty directory of its own below the system's temporary directory. It is deleted
+/// with everything in it when dropped, also when the test panics.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// `name` has to be unique among the tests, the process id keeps parallel runs apart.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        // Left over from a run that was killed
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A glTF file with one triangle and its buffer in a data URI, drawn with `indices`. With
+/// `joint` every vertex is skinned to that joint of a skin with only one.
+pub fn triangle_gltf(indices: &[u32], joint: Option<u8>) -> String {
+    use base64::Engine;
+
+    let mut data = Vec::new();
+    for position in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+        data.extend(position.iter().flat_map(|v| v.to_le_bytes()));
+    }
+    for _ in 0..3 {
+        data.extend([0.0f32, 0.0, 1.0].iter().flat_map(|v| v.to_le_bytes()));
+    }
+    data.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+    let mut views = vec![(0, 36), (36, 36), (72, indices.len() * 4)];
+    let accessor = |view: usize, component_type: u32, count: usize, kind: &str| {
+        format!(
+            r#"{{"bufferView": {}, "componentType": {}, "count": {}, "type": "{}"}}"#,
+            view, component_type, count, kind
+        )
+    };
+    // Positions need their bounds
+    let mut accessors = vec![
+        accessor(0, 5126, 3, "VEC3").replace('}', r#", "min": [0, 0, 0], "max": [1, 1, 0]}"#),
+        accessor(1, 5126, 3, "VEC3"),
+        accessor(2, 5125, indices.len(), "SCALAR"),
+    ];
+    let mut attributes = r#""POSITION": 0, "NORMAL": 1"#.to_string();
+    let mut skin = String::new();
+    if let Some(joint) = joint {
+        let start = data.len();
+        for _ in 0..3 {
+            data.extend([joint, 0, 0, 0]);
+        }
+        for _ in 0..3 {
+            data.extend([1.0f32, 0.0, 0.0, 0.0].iter().flat_map(|v| v.to_le_bytes()));
+        }
+        views.extend([(start, 12), (start + 12, 48)]);
+        accessors.extend([accessor(3, 5121, 3, "VEC4"), accessor(4, 5126, 3, "VEC4")]);
+        attributes += r#", "JOINTS_0": 3, "WEIGHTS_0": 4"#;
+        skin = r#", "skin": 0"#.to_string();
+    }
+    let views = views
+        .iter()
+        .map(|(offset, length)| {
+            format!(
+                r#"{{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+                offset, length
+            )
+        })
+        .collect::<Vec<_>>();
+    let uri = format!(
+        "data:application/octet-stream;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(&data)
+    );
+    format!(
+        r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0, 1]}}],
+            "nodes": [{{"mesh": 0{skin}}}, {{"name": "root"}}],
+            "skins": [{{"joints": [1]}}],
+            "meshes": [{{"primitives": [{{"attributes": {{{attributes}}}, "indices": 2}}]}}],
+            "accessors": [{accessors}],
+            "bufferViews": [{views}],
+            "buffers": [{{"byteLength": {length}, "uri": "{uri}"}}]
+        }}"#,
+        accessors = accessors.join(", "),
+        views = views.join(", "),
+        length = data.len(),
+    )
+}
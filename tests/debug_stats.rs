@@ -0,0 +1,88 @@
+//! The debug screen statistics are plain data, so they can be checked without rendering.
+
+mod common;
+
+use cgmath::{Deg, Point3, Rad, Vector2, Vector3};
+use std::time::Duration;
+use tutorial12_camera::{
+    block::BlockFace,
+    debug::{facing, DebugStats, FrameTimes, FRAME_HISTORY},
+    renderer::RenderStats,
+    world::World,
+};
+
+#[test]
+fn frame_times_keep_a_bounded_history() {
+    let mut frame_times = FrameTimes::new();
+    assert_eq!(frame_times.fps(), 0.0);
+
+    for _ in 0..FRAME_HISTORY {
+        frame_times.push(Duration::from_millis(50));
+    }
+    for _ in 0..FRAME_HISTORY {
+        frame_times.push(Duration::from_millis(10));
+    }
+    assert_eq!(frame_times.iter().count(), FRAME_HISTORY);
+    assert_eq!(frame_times.average(), Duration::from_millis(10));
+    assert_eq!(frame_times.max(), Duration::from_millis(10));
+    assert!((frame_times.fps() - 100.0).abs() < 0.01);
+}
+
+#[test]
+fn facing_follows_yaw() {
+    assert_eq!(facing(Rad::from(Deg(0.0))), BlockFace::East);
+    assert_eq!(facing(Rad::from(Deg(90.0))), BlockFace::South);
+    assert_eq!(facing(Rad::from(Deg(-90.0))), BlockFace::North);
+    assert_eq!(facing(Rad::from(Deg(200.0))), BlockFace::West);
+}
+
+#[tokio::test]
+async fn stats_describe_player_and_world() {
+    let renderer = common::headless_renderer(64, 64).await;
+    let world = World::new(&renderer).await;
+
+    let mut frame_times = FrameTimes::new();
+    frame_times.push(Duration::from_millis(20));
+    let render = RenderStats {
+        draw_calls: 12,
+        triangles: 3456,
+        buffer_memory: 2048,
+        chunks: [20, 12, 8, 0],
+        occluded: 300,
+        entities: 5,
+        particles: 64,
+    };
+    let target = Some((Vector3::new(4, 0, 6), BlockFace::Top));
+    let stats = DebugStats::collect(
+        &world,
+        &frame_times,
+        Point3::new(40.4, 5.0, -0.6),
+        Rad::from(Deg(-90.0)),
+        Rad::from(Deg(-20.0)),
+        target,
+        render,
+    );
+
+    assert_eq!(stats.fps, 50.0);
+    // Blocks are two units wide and centred on even coordinates
+    assert_eq!(stats.block, Vector3::new(40, 6, 0));
+    assert_eq!(stats.chunk, Vector2::new(1, 0));
+    assert_eq!(stats.facing, BlockFace::North);
+    assert_eq!(stats.target, target);
+    // The generated floor is 100x100 blocks, 2 units apart
+    assert_eq!(stats.blocks, 100 * 100);
+    assert_eq!(stats.loaded_chunks, 7 * 7);
+    assert_eq!(stats.render, render);
+
+    let lines = stats.lines();
+    assert!(lines.iter().any(|line| line == "Block: 40 6 0"));
+    assert!(lines
+        .iter()
+        .any(|line| line == "Draw calls: 12, triangles: 3456"));
+    assert!(lines
+        .iter()
+        .any(|line| line == "Chunks drawn: 20 / 12 / 8 / 0 (full detail to 8x merged)"));
+    assert!(lines.iter().any(|line| line == "Occluded: 300"));
+    assert!(lines.iter().any(|line| line == "Entities: 5"));
+    assert!(lines.iter().any(|line| line == "Particles: 64"));
+}
@@ -0,0 +1,154 @@
+//! Entities need model ids, so every test loads models with a headless renderer.
+
+mod common;
+
+use cgmath::{Deg, InnerSpace, Point3, Quaternion, Rad, Rotation3, Vector3, Vector4};
+use tutorial12_camera::{
+    entity::{Entities, Entity},
+    offscreen::OffscreenTarget,
+    resources::{self, AssetSources},
+    world::World,
+};
+
+#[tokio::test]
+async fn models_load_once_and_entities_batch_by_model() {
+    let renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let cube = world.models.load("cube.obj", &renderer).await.unwrap();
+    let pyramid = world.models.load("pyramid.obj", &renderer).await.unwrap();
+    assert_eq!(
+        world.models.load("cube.obj", &renderer).await.unwrap(),
+        cube
+    );
+    assert_ne!(cube, pyramid);
+    assert_eq!(world.models.len(), 2);
+    assert!(world.models.load("missing.obj", &renderer).await.is_err());
+
+    // Scaled, then rotated, then moved
+    let entity = Entity {
+        rotation: Quaternion::from_angle_y(Deg(90.0)),
+        scale: Vector3::new(2.0, 1.0, 1.0),
+        ..Entity::new(cube, Vector3::new(10.0, 0.0, 0.0))
+    };
+    let corner = entity.transform() * Vector4::new(1.0, 1.0, 0.0, 1.0);
+    assert!((corner - Vector4::new(10.0, 1.0, -2.0, 1.0)).magnitude() < 1e-5);
+    // New entities start out unrotated, so rotations build on them
+    let mut turned = Entity {
+        scale: Vector3::new(2.0, 1.0, 1.0),
+        ..Entity::new(cube, Vector3::new(10.0, 0.0, 0.0))
+    };
+    turned.rotation = turned.rotation * Quaternion::from_angle_y(Deg(90.0));
+    assert_eq!(turned.transform(), entity.transform());
+
+    let mut entities = Entities::default();
+    let first = entities.spawn(Entity::new(pyramid, Vector3::new(0.0, 0.0, 0.0)));
+    entities.spawn(Entity::new(cube, Vector3::new(4.0, 0.0, 0.0)));
+    let far = entities.spawn(Entity::new(pyramid, Vector3::new(100.0, 0.0, 0.0)));
+    entities.spawn(entity);
+
+    let batches = entities.batches(|_| true);
+    let counts: Vec<_> = batches.iter().map(|(model, b)| (*model, b.len())).collect();
+    assert_eq!(counts, [(cube, 2), (pyramid, 2)]);
+    let near = entities.batches(|entity| entity.position.x < 50.0);
+    let counts: Vec<_> = near.iter().map(|(model, b)| (*model, b.len())).collect();
+    assert_eq!(counts, [(cube, 2), (pyramid, 1)]);
+
+    entities.get_mut(first).unwrap().position.y = 6.0;
+    assert_eq!(entities.get(first).unwrap().position.y, 6.0);
+    assert_eq!(entities.remove(far).unwrap().model, pyramid);
+    assert!(entities.get(far).is_none());
+    assert_eq!(entities.len(), 3);
+}
+
+#[tokio::test]
+async fn entities_are_drawn_with_their_own_models() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+    let pyramid = world.models.load("pyramid.obj", &renderer).await.unwrap();
+
+    renderer.update(
+        &Point3::new(10.0, 8.0, 20.0),
+        Rad(-0.5),
+        Rad::from(Deg(-90.0)),
+    );
+    let before = renderer.render(&world, &target.view);
+    let empty = target.read_image(&renderer).unwrap();
+
+    for x in 0..3 {
+        world.entities.spawn(Entity::new(
+            pyramid,
+            Vector3::new(6.0 + x as f32 * 4.0, 2.0, 10.0),
+        ));
+    }
+    // Far out of reach of the render distance
+    world
+        .entities
+        .spawn(Entity::new(pyramid, Vector3::new(5000.0, 2.0, 10.0)));
+    let after = renderer.render(&world, &target.view);
+    assert_eq!(after.entities, 3);
+    // Six triangles each, drawn into every shadow cascade and the main pass
+    let cascades = 4;
+    assert_eq!(after.triangles - before.triangles, 3 * 6 * (cascades + 1));
+    assert_ne!(target.read_image(&renderer).unwrap(), empty);
+}
+
+#[tokio::test]
+async fn untextured_models_are_lit() {
+    let pack = common::TempDir::new("untextured");
+    // The cube with its texture coordinates, but only a colour as material
+    let cube = String::from_utf8(resources::embedded("cube.obj").unwrap().to_vec()).unwrap();
+    std::fs::write(
+        pack.join("painted.obj"),
+        cube.replace("mtllib cube.mtl", "mtllib paint.mtl")
+            .replace("usemtl Material", "usemtl Paint"),
+    )
+    .unwrap();
+    std::fs::write(pack.join("paint.mtl"), "newmtl Paint\nKd 1 0 0\n").unwrap();
+    // A triangle without any texture coordinates
+    let triangle = common::triangle_gltf(&[0, 1, 2], None)
+        .replace(
+            r#""asset": {"version": "2.0"},"#,
+            concat!(
+                r#""asset": {"version": "2.0"},"#,
+                r#""materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1]}}],"#
+            ),
+        )
+        .replace(r#""indices": 2}"#, r#""indices": 2, "material": 0}"#);
+    std::fs::write(pack.join("painted.gltf"), triangle).unwrap();
+    resources::set_asset_sources(AssetSources {
+        root: None,
+        packs: vec![pack.to_path_buf()],
+    });
+
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+    renderer.update(
+        &Point3::new(10.0, 8.0, 20.0),
+        Rad(-0.5),
+        Rad::from(Deg(-90.0)),
+    );
+    for (name, position, scale) in [
+        ("painted.obj", Vector3::new(10.0, 4.0, 12.0), 2.0),
+        ("painted.gltf", Vector3::new(8.0, 2.0, 12.0), 4.0),
+    ] {
+        let model = world.models.load(name, &renderer).await.unwrap();
+        let entity = world.entities.spawn(Entity {
+            scale: Vector3::new(scale, scale, scale),
+            ..Entity::new(model, position)
+        });
+        renderer.render(&world, &target.view);
+        let image = target.read_image(&renderer).unwrap();
+        // A tangent of zero length turns the lighting into NaN, which leaves only the
+        // ambient light and every face equally dark
+        let lit = image
+            .pixels()
+            .filter(|pixel| pixel[0] > 120 && pixel[1] < 10 && pixel[2] < 10)
+            .count();
+        assert!(lit > 64, "only {} lit red pixels of {}", lit, name);
+        world.entities.remove(entity);
+    }
+
+    resources::set_asset_sources(AssetSources::default());
+}
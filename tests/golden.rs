@@ -0,0 +1,157 @@
+//! Renders fixed scenes offscreen and compares them against the reference images in
+//! `tests/golden`. Run with `UPDATE_GOLDEN=1` to write new references after an intended
+//! change to the output. On a mismatch the actual frame and a diff image end up in the
+//! test temp directory, the failure message has the paths.
+
+mod common;
+
+use cgmath::{Deg, Point3, Rad, Vector3};
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use tutorial12_camera::{block::BlockKind, offscreen::OffscreenTarget, world::World};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+// Largest difference per colour channel that still counts as the same pixel
+const TOLERANCE: u8 = 8;
+// Fraction of pixels that may be off by more than the tolerance, software rasterizers
+// do not all agree on edges
+const MAX_MISMATCHED: f32 = 0.005;
+
+struct Scene {
+    name: &'static str,
+    time_of_day: f32,
+    position: Point3<f32>,
+    pitch: Deg<f32>,
+    yaw: Deg<f32>,
+    blocks: &'static [([i32; 3], BlockKind)],
+}
+
+async fn render(scene: &Scene) -> RgbaImage {
+    let mut renderer = common::headless_renderer(WIDTH, HEIGHT).await;
+    let target = OffscreenTarget::new(&renderer);
+
+    let mut world = World::new(&renderer).await;
+    for (position, kind) in scene.blocks {
+        world.place(Vector3::from(*position), *kind);
+    }
+
+    renderer.sky.time_of_day.time = scene.time_of_day;
+    renderer.set_light((100.0, 30.0, 100.0).into(), (1.0, 1.0, 1.0).into());
+    renderer.update(
+        &scene.position,
+        Rad::from(scene.pitch),
+        Rad::from(scene.yaw),
+    );
+    renderer.render(&world, &target.view);
+    target.read_image(&renderer).unwrap()
+}
+
+/// Marks every pixel outside the tolerance in red on top of a faded copy of `actual`.
+/// Returns the diff image and the number of mismatched pixels.
+fn diff(expected: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, usize) {
+    let mut mismatched = 0;
+    let image = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let e = expected.get_pixel(x, y);
+        let matches = a.0.iter().zip(e.0).all(|(a, e)| a.abs_diff(e) <= TOLERANCE);
+        if matches {
+            let gray = (a[0] as u16 + a[1] as u16 + a[2] as u16) / 3 / 4;
+            Rgba([gray as u8, gray as u8, gray as u8, 255])
+        } else {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        }
+    });
+    (image, mismatched)
+}
+
+async fn check(scene: Scene) {
+    let actual = render(&scene).await;
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", scene.name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&reference).unwrap();
+        return;
+    }
+
+    let expected = image::open(&reference)
+        .unwrap_or_else(|e| {
+            panic!(
+                "Could not open {}: {e}, run with UPDATE_GOLDEN=1 to create it",
+                reference.display()
+            )
+        })
+        .to_rgba8();
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "{} has the wrong size",
+        reference.display()
+    );
+
+    let (diff_image, mismatched) = diff(&expected, &actual);
+    let allowed = (WIDTH * HEIGHT) as f32 * MAX_MISMATCHED;
+    if mismatched as f32 > allowed {
+        let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{}-actual.png", scene.name));
+        let diff_path = out_dir.join(format!("{}-diff.png", scene.name));
+        actual.save(&actual_path).unwrap();
+        diff_image.save(&diff_path).unwrap();
+        panic!(
+            "{}: {mismatched} pixels differ from {}, see {} and {}",
+            scene.name,
+            reference.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[tokio::test]
+async fn dirt_floor_at_noon() {
+    check(Scene {
+        name: "dirt_floor_at_noon",
+        time_of_day: 0.5,
+        position: Point3::new(-6.0, 8.0, -6.0),
+        pitch: Deg(-25.0),
+        yaw: Deg(45.0),
+        blocks: &[],
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn block_layers() {
+    check(Scene {
+        name: "block_layers",
+        time_of_day: 0.45,
+        position: Point3::new(0.0, 6.0, -4.0),
+        pitch: Deg(-35.0),
+        yaw: Deg(60.0),
+        blocks: &[
+            ([2, 2, 4], BlockKind::Cobble),
+            ([4, 2, 6], BlockKind::Glass),
+            ([6, 2, 4], BlockKind::Water),
+            ([2, 2, 8], BlockKind::Leaves),
+            ([6, 4, 8], BlockKind::Dirt),
+        ],
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn fog_at_sunset() {
+    check(Scene {
+        name: "fog_at_sunset",
+        time_of_day: 0.74,
+        position: Point3::new(100.0, 3.0, 100.0),
+        pitch: Deg(-5.0),
+        yaw: Deg(-90.0),
+        blocks: &[],
+    })
+    .await;
+}
@@ -0,0 +1,134 @@
+//! Downsampling and level selection are plain data, only the last test renders.
+
+mod common;
+
+use cgmath::{Point3, Rad, Vector2, Vector3};
+use tutorial12_camera::{
+    block::BlockKind,
+    lod::{chunk_distance, downsample, LodSettings, LOD_LEVELS},
+    offscreen::OffscreenTarget,
+    world::{chunk_of, World, CHUNK_SIZE},
+};
+
+#[test]
+fn levels_double_with_the_distance() {
+    let settings = LodSettings {
+        render_distance: 1000.0,
+        detail_distance: 100.0,
+    };
+    assert_eq!(settings.level(0.0), Some(0));
+    assert_eq!(settings.level(99.0), Some(0));
+    assert_eq!(settings.level(100.0), Some(1));
+    assert_eq!(settings.level(250.0), Some(2));
+    assert_eq!(settings.level(400.0), Some(LOD_LEVELS));
+    assert_eq!(settings.level(1000.0), Some(LOD_LEVELS));
+    assert_eq!(settings.level(1000.1), None);
+}
+
+#[test]
+fn chunk_distance_is_zero_inside() {
+    let chunk = Vector2::new(1, -1);
+    assert_eq!(chunk_distance(chunk, Point3::new(40.0, 50.0, -10.0)), 0.0);
+    assert_eq!(chunk_distance(chunk, Point3::new(10.0, 0.0, -10.0)), 22.0);
+    assert_eq!(chunk_distance(chunk, Point3::new(67.0, 0.0, 4.0)), 5.0);
+}
+
+#[test]
+fn flat_ground_keeps_its_shape() {
+    // One chunk of floor, 16x16 blocks two units apart
+    let floor: Vec<_> = (0..16)
+        .flat_map(|x| (0..16).map(move |z| (Vector3::new(x * 2, 0, z * 2), BlockKind::Dirt)))
+        .collect();
+
+    for level in 1..=LOD_LEVELS {
+        let lod_blocks = downsample(floor.iter().copied(), level);
+        let cells_per_axis = 16 >> level;
+        assert_eq!(lod_blocks.len(), cells_per_axis * cells_per_axis);
+        for lod_block in &lod_blocks {
+            // As thick as the floor and never reaching past the chunk
+            assert_eq!((lod_block.min.y, lod_block.max.y), (-1.0, 1.0));
+            assert!(lod_block.min.x >= -1.0 && lod_block.max.x <= 31.0);
+            assert_eq!(lod_block.max.x - lod_block.min.x, (2 << level) as f32);
+            assert_eq!(lod_block.kind, BlockKind::Dirt);
+        }
+    }
+}
+
+#[test]
+fn boxes_fit_their_blocks_and_take_the_common_kind() {
+    let blocks = [
+        (Vector3::new(0, 0, 0), BlockKind::Water),
+        (Vector3::new(2, 0, 0), BlockKind::Water),
+        (Vector3::new(0, 0, 2), BlockKind::Cobble),
+        (Vector3::new(2, 2, 0), BlockKind::Water),
+        (Vector3::new(4, 0, 0), BlockKind::Glass),
+        (Vector3::new(4, 0, 2), BlockKind::Leaves),
+    ];
+
+    let lod_blocks = downsample(blocks, 1);
+    assert_eq!(lod_blocks.len(), 3);
+    // Layers of one cell stay apart, the upper one only covers its single block
+    assert_eq!(lod_blocks[0].min, Vector3::new(-1.0, -1.0, -1.0));
+    assert_eq!(lod_blocks[0].max, Vector3::new(3.0, 1.0, 3.0));
+    assert_eq!(lod_blocks[0].kind, BlockKind::Water);
+    assert_eq!(lod_blocks[1].min, Vector3::new(1.0, 1.0, -1.0));
+    assert_eq!(lod_blocks[1].max, Vector3::new(3.0, 3.0, 1.0));
+    // A tie goes to the kind listed first
+    assert_eq!(lod_blocks[2].min, Vector3::new(3.0, -1.0, -1.0));
+    assert_eq!(lod_blocks[2].max, Vector3::new(5.0, 1.0, 3.0));
+    assert_eq!(lod_blocks[2].kind, BlockKind::Glass);
+}
+
+#[test]
+fn matching_layers_merge_into_one_box() {
+    // A solid 2x2 pillar, four blocks high
+    let pillar: Vec<_> = (0..4)
+        .flat_map(|y| {
+            [(0, 0), (2, 0), (0, 2), (2, 2)]
+                .map(|(x, z)| (Vector3::new(x, y * 2, z), BlockKind::Cobble))
+        })
+        .collect();
+
+    let lod_blocks = downsample(pillar, 1);
+    assert_eq!(lod_blocks.len(), 1);
+    assert_eq!(lod_blocks[0].min, Vector3::new(-1.0, -1.0, -1.0));
+    assert_eq!(lod_blocks[0].max, Vector3::new(3.0, 7.0, 3.0));
+}
+
+#[tokio::test]
+async fn distant_chunks_draw_fewer_triangles() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+
+    // Downsampled levels follow edits
+    let far = Vector3::new(CHUNK_SIZE * 10, 0, 0);
+    world.place(far, BlockKind::Cobble);
+    assert_eq!(world.chunks()[&chunk_of(far)].lod(LOD_LEVELS).len(), 1);
+    world.destroy(&far);
+    assert!(!world.chunks().contains_key(&chunk_of(far)));
+
+    let mut render = |settings: LodSettings| {
+        renderer.set_lod_settings(settings);
+        renderer.update(&Point3::new(-10.0, 10.0, -10.0), Rad(-0.3), Rad(0.8));
+        renderer.render(&world, &target.view)
+    };
+    let full = render(LodSettings {
+        render_distance: 1000.0,
+        detail_distance: 1000.0,
+    });
+    let reduced = render(LodSettings {
+        render_distance: 1000.0,
+        detail_distance: 32.0,
+    });
+    let culled = render(LodSettings {
+        render_distance: 64.0,
+        detail_distance: 32.0,
+    });
+
+    assert_eq!(full.chunks, [7 * 7, 0, 0, 0]);
+    assert_eq!(reduced.chunks.iter().sum::<u32>(), 7 * 7);
+    assert!(reduced.chunks[LOD_LEVELS] > 0);
+    assert!(reduced.triangles * 4 < full.triangles);
+    assert!(culled.chunks.iter().sum::<u32>() < 7 * 7);
+}
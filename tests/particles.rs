@@ -0,0 +1,141 @@
+//! The simulation is plain data and deterministic, only the last test renders.
+
+mod common;
+
+use cgmath::{Point3, Rad, Vector3};
+use std::time::Duration;
+use tutorial12_camera::{
+    block::{BlockFace, BlockKind},
+    offscreen::OffscreenTarget,
+    particles::{Emitter, ParticleSystem},
+    world::World,
+};
+
+const STEP: Duration = Duration::from_millis(50);
+
+fn nothing_solid(_: Vector3<f32>) -> bool {
+    false
+}
+
+fn simulate(seed: u64, steps: usize) -> ParticleSystem {
+    let mut system = ParticleSystem::new(seed);
+    system.break_block(Vector3::new(0.0, 10.0, 0.0), [0.0, 0.0, 0.5, 0.5]);
+    system.add_emitter(Emitter::smoke(Vector3::new(4.0, 0.0, 0.0)));
+    for _ in 0..steps {
+        system.update(STEP, nothing_solid);
+    }
+    system
+}
+
+#[test]
+fn the_same_seed_gives_the_same_particles() {
+    assert_eq!(simulate(7, 10).particles(), simulate(7, 10).particles());
+    assert_ne!(simulate(7, 10).particles(), simulate(8, 10).particles());
+}
+
+#[test]
+fn broken_blocks_fall_apart_into_pieces_of_their_texture() {
+    let mut system = ParticleSystem::new(1);
+    let uv_rect = [0.25, 0.5, 0.5, 0.75];
+    system.break_block(Vector3::new(0.0, 10.0, 0.0), uv_rect);
+    assert_eq!(system.particles().len(), 64);
+    for particle in system.particles() {
+        let [min_u, min_v, max_u, max_v] = particle.uv_rect;
+        assert!(min_u >= uv_rect[0] && max_u <= uv_rect[2] + 1e-6);
+        assert!(min_v >= uv_rect[1] && max_v <= uv_rect[3] + 1e-6);
+        assert!((max_u - min_u - 0.0625).abs() < 1e-6);
+        // Starting inside the block
+        assert!((particle.position.y - 10.0).abs() < 1.0);
+    }
+
+    // Gravity wins over the initial push upwards and everything is gone in the end
+    for _ in 0..10 {
+        system.update(STEP, nothing_solid);
+    }
+    assert!(system.particles().iter().all(|p| p.velocity.y < 4.0));
+    for _ in 0..20 {
+        system.update(STEP, nothing_solid);
+    }
+    assert!(system.particles().is_empty());
+}
+
+#[test]
+fn emitters_spawn_at_their_rate_until_removed() {
+    let mut system = ParticleSystem::new(3);
+    let emitter = Emitter {
+        rate: 4.0,
+        lifetime: 10.0..10.0,
+        ..Emitter::torch(Vector3::new(0.0, 0.0, 0.0))
+    };
+    let id = system.add_emitter(emitter);
+    for _ in 0..4 {
+        system.update(Duration::from_millis(250), nothing_solid);
+    }
+    assert_eq!(system.particles().len(), 4);
+
+    system.emitter_mut(id).unwrap().position = Vector3::new(100.0, 0.0, 0.0);
+    system.update(Duration::from_millis(500), nothing_solid);
+    assert_eq!(system.particles().len(), 6);
+    assert!(system.particles()[5].position.x > 99.0);
+
+    assert!(system.remove_emitter(id).is_some());
+    assert!(system.emitter_mut(id).is_none());
+    system.update(Duration::from_secs(1), nothing_solid);
+    assert_eq!(system.particles().len(), 6);
+}
+
+#[test]
+fn particles_land_on_blocks_and_rain_vanishes() {
+    let ground = |position: Vector3<f32>| position.y < 0.0;
+    let mut system = ParticleSystem::new(5);
+    system.break_block(Vector3::new(0.0, 2.0, 0.0), [0.0, 0.0, 1.0, 1.0]);
+    for _ in 0..8 {
+        system.update(STEP, ground);
+    }
+    assert!(!system.particles().is_empty());
+    assert!(system.particles().iter().all(|p| p.position.y >= 0.0));
+    assert!(system
+        .particles()
+        .iter()
+        .any(|p| p.velocity.y == 0.0 && p.position.y < 0.5));
+
+    let mut system = ParticleSystem::new(5);
+    system.burst(&Emitter::rain(Vector3::new(0.0, 4.0, 0.0), 10.0), 50);
+    system.update(STEP, ground);
+    assert_eq!(system.particles().len(), 50);
+    for _ in 0..10 {
+        system.update(STEP, ground);
+    }
+    assert!(system.particles().is_empty());
+}
+
+#[test]
+fn smoke_fades_out() {
+    let mut system = ParticleSystem::new(9);
+    system.burst(&Emitter::smoke(Vector3::new(0.0, 0.0, 0.0)), 1);
+    let alpha = |system: &ParticleSystem| system.particles()[0].current_color()[3];
+    let start = alpha(&system);
+    system.update(Duration::from_secs(1), nothing_solid);
+    assert!(alpha(&system) < start);
+    assert!(system.particles()[0].position.y > 0.0);
+}
+
+#[tokio::test]
+async fn particles_are_drawn_with_the_world() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+
+    let coords = Vector3::new(10, 0, 10);
+    let block = world.destroy(&coords).unwrap();
+    assert_eq!(block.kind, BlockKind::Dirt);
+    assert!(world.destroy(&coords).is_none());
+    let uv_rect = world.atlas().block_uv(block.kind, BlockFace::North);
+    world.particles.break_block(block.position, uv_rect);
+    world.update(&renderer.queue, STEP);
+
+    renderer.update(&Point3::new(10.0, 6.0, 20.0), Rad(-0.5), Rad(-1.5));
+    let stats = renderer.render(&world, &target.view);
+    assert_eq!(stats.particles, 64);
+    target.read_image(&renderer).unwrap();
+}
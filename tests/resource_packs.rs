@@ -0,0 +1,100 @@
+//! Packs are temporary directories. Only the last test changes where the game loads its
+//! assets from, so the other tests in this file are not affected by it.
+
+mod common;
+
+use image::RgbaImage;
+use std::path::Path;
+use tutorial12_camera::{
+    block::{BlockFace, BlockKind},
+    resources::{self, AssetSources},
+    shaders::{self, Shader},
+    world::World,
+};
+
+fn write(dir: &Path, file_name: &str, contents: &[u8]) {
+    let path = dir.join(file_name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn the_first_pack_with_a_file_wins() {
+    let dir = common::TempDir::new("resource-packs-order");
+    let (root, high, low) = (dir.join("root"), dir.join("high"), dir.join("low"));
+    write(&root, "cube.mtl", b"root");
+    write(&root, "notes.txt", b"root");
+    write(&low, "cube.mtl", b"low");
+    write(&low, "fonts/notes.txt", b"low");
+    write(&high, "cube.mtl", b"high");
+    std::fs::create_dir_all(&high).unwrap();
+
+    let sources = AssetSources {
+        root: Some(root.clone()),
+        packs: vec![high.clone(), low.clone()],
+    };
+    assert_eq!(sources.read("cube.mtl").unwrap(), b"high");
+    assert_eq!(sources.find("cube.mtl"), Some(high.join("cube.mtl")));
+    assert_eq!(sources.read("notes.txt").unwrap(), b"root");
+    assert_eq!(sources.read("fonts/notes.txt").unwrap(), b"low");
+    // Everything else comes from the copies built into the binary
+    assert_eq!(sources.find("cube.obj"), None);
+    assert_eq!(
+        sources.read("cube.obj").unwrap(),
+        resources::embedded("cube.obj").unwrap()
+    );
+    assert!(resources::embedded("fonts/DejaVuSansMono.ttf").is_some());
+    assert!(sources.read("missing.png").is_err());
+
+    assert!(AssetSources::default().read("dirt.png").is_ok());
+}
+
+#[tokio::test]
+async fn packs_replace_textures_models_and_shaders() {
+    let dir = common::TempDir::new("resource-packs-game");
+    let pack = dir.join("pack");
+    let mut red = Vec::new();
+    image::DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 255])))
+        .write_to(
+            &mut std::io::Cursor::new(&mut red),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    write(&pack, "dirt.png", &red);
+    // The pack's model uses the material of the built in pyramid
+    let pyramid = resources::embedded("pyramid.obj").unwrap();
+    write(&pack, "models/tent.obj", pyramid);
+    write(
+        &pack,
+        "models/pyramid.mtl",
+        resources::embedded("pyramid.mtl").unwrap(),
+    );
+    write(&pack, "shaders/sky.wgsl", include_bytes!("../src/sky.wgsl"));
+    write(&pack, "shaders/outline.wgsl", b"this is not WGSL");
+    resources::set_asset_sources(AssetSources {
+        root: None,
+        packs: vec![pack.clone()],
+    });
+
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let atlas = world.atlas();
+    let region = atlas
+        .region(BlockKind::Dirt.texture(BlockFace::Top))
+        .unwrap();
+    let pixel = atlas.mips[0].get_pixel(region.x + 4, region.y + 4);
+    assert_eq!(pixel.0, [255, 0, 0, 255]);
+
+    let tent = world.models.load("models/tent.obj", &renderer).await;
+    assert!(tent.is_ok(), "{:?}", tent.err());
+
+    let overrides = shaders::overrides(&resources::asset_sources());
+    let shaders: Vec<_> = overrides.iter().map(|(shader, _)| *shader).collect();
+    assert_eq!(shaders, [Shader::Sky, Shader::Outline]);
+    for (shader, source) in overrides {
+        let applied = renderer.reload_shader(shader, &source.unwrap());
+        assert_eq!(applied.is_ok(), shader == Shader::Sky);
+    }
+
+    resources::set_asset_sources(AssetSources::default());
+}
@@ -0,0 +1,74 @@
+//! Screenshot file names and turning the copied texture rows into an image, both on the CPU.
+
+use std::time::{Duration, UNIX_EPOCH};
+use tutorial12_camera::{
+    offscreen::{image_from_rows, padded_bytes_per_row},
+    screenshot::{civil_from_days, timestamp},
+};
+
+#[test]
+fn days_convert_to_dates() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    assert_eq!(civil_from_days(59), (1970, 3, 1));
+    // Leap day of a year divisible by 400
+    assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    assert_eq!(civil_from_days(11017), (2000, 3, 1));
+    assert_eq!(civil_from_days(-135080), (1600, 3, 1));
+}
+
+#[test]
+fn screenshots_are_named_after_the_time() {
+    let time = UNIX_EPOCH + Duration::from_millis(1_680_352_205_123);
+    assert_eq!(timestamp(time), "2023-04-01_12-30-05.123");
+    assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01_00-00-00.000");
+}
+
+// Rows of `width` texels with every texel set by `texel`, padded with garbage
+fn padded_rows(width: u32, height: u32, texel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+    let padded = padded_bytes_per_row(width) as usize;
+    let mut data = vec![0xAA; padded * height as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let start = y as usize * padded + x as usize * 4;
+            data[start..start + 4].copy_from_slice(&texel(x, y));
+        }
+    }
+    data
+}
+
+#[test]
+fn padding_is_dropped_from_every_row() {
+    // 12 bytes of texels in a 256 byte row
+    assert_eq!(padded_bytes_per_row(3), 256);
+    assert_eq!(padded_bytes_per_row(64), 256);
+    assert_eq!(padded_bytes_per_row(65), 512);
+
+    let data = padded_rows(3, 2, |x, y| [x as u8, y as u8, 10, 255]);
+    let image = image_from_rows(&data, 3, 2, wgpu::TextureFormat::Rgba8UnormSrgb).unwrap();
+    assert_eq!(image.dimensions(), (3, 2));
+    for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel.0, [x as u8, y as u8, 10, 255]);
+    }
+}
+
+#[test]
+fn bgra_is_swizzled_and_linear_is_encoded() {
+    let data = padded_rows(3, 1, |_, _| [64, 128, 255, 200]);
+
+    let image = image_from_rows(&data, 3, 1, wgpu::TextureFormat::Bgra8UnormSrgb).unwrap();
+    assert_eq!(image.get_pixel(2, 0).0, [255, 128, 64, 200]);
+
+    // Alpha is never encoded
+    let image = image_from_rows(&data, 3, 1, wgpu::TextureFormat::Bgra8Unorm).unwrap();
+    assert_eq!(image.get_pixel(2, 0).0, [255, 188, 137, 200]);
+    let image = image_from_rows(&data, 3, 1, wgpu::TextureFormat::Rgba8Unorm).unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [137, 188, 255, 200]);
+}
+
+#[test]
+fn bad_readbacks_are_errors() {
+    let data = padded_rows(3, 2, |_, _| [0; 4]);
+    assert!(image_from_rows(&data, 3, 3, wgpu::TextureFormat::Rgba8UnormSrgb).is_err());
+    assert!(image_from_rows(&data, 3, 2, wgpu::TextureFormat::Rgba16Float).is_err());
+}
@@ -0,0 +1,115 @@
+//! Hot reloading has to reject broken shaders without losing the pipelines in use.
+
+mod common;
+
+use std::time::{Duration, SystemTime};
+use tutorial12_camera::{
+    offscreen::OffscreenTarget,
+    shaders::{self, Shader, ShaderWatcher},
+    world::World,
+};
+
+fn shader_source(shader: Shader) -> String {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join(shader.file_name());
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn shipped_shaders_validate() {
+    for shader in Shader::ALL {
+        shaders::validate(shader, &shader_source(shader)).unwrap();
+    }
+}
+
+#[test]
+fn bindings_have_to_match_the_layouts() {
+    // No bind group layout has this binding
+    let source = shader_source(Shader::Sky).replace("@binding(0)", "@binding(5)");
+    let error = shaders::validate(Shader::Sky, &source).unwrap_err();
+    assert!(format!("{:#}", error).contains("@group(0) @binding(5)"));
+
+    // The skin joints are a uniform buffer, not a storage buffer
+    let source = shader_source(Shader::Shadow).replace("var<uniform> skin", "var<storage> skin");
+    let error = shaders::validate(Shader::Shadow, &source).unwrap_err();
+    assert!(format!("{:#}", error).contains("@group(1) @binding(0)"));
+
+    // The outline pipeline only has two bind groups
+    let source = shader_source(Shader::Outline).replace("@group(0)", "@group(3)");
+    assert!(shaders::validate(Shader::Outline, &source).is_err());
+}
+
+#[test]
+fn vertex_inputs_have_to_match_the_layouts() {
+    // The sprite vertices have no fourth attribute
+    let source = shader_source(Shader::Overlay).replace("@location(2) color", "@location(3) color");
+    let error = shaders::validate(Shader::Overlay, &source).unwrap_err();
+    assert!(format!("{:#}", error).contains("@location(3)"));
+
+    // Vertex entry points without vertex buffers in the table are not checked otherwise
+    let source = shader_source(Shader::Outline)
+        + "@vertex\nfn vs_point() -> @builtin(position) vec4<f32> {\n    return vec4<f32>(0.0);\n}\n";
+    let error = shaders::validate(Shader::Outline, &source).unwrap_err();
+    assert!(format!("{:#}", error).contains("vs_point"));
+}
+
+#[tokio::test]
+async fn broken_shaders_keep_the_old_pipelines() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    for shader in Shader::ALL {
+        renderer
+            .reload_shader(shader, &shader_source(shader))
+            .unwrap();
+    }
+
+    // Caught by naga before wgpu sees it
+    let error = renderer
+        .reload_shader(Shader::Sky, "fn broken(")
+        .unwrap_err();
+    assert!(format!("{:#}", error).contains("sky.wgsl"));
+
+    // Valid WGSL that does not fit the pipeline layout
+    let source = shader_source(Shader::Sky).replace("@binding(0)", "@binding(5)");
+    assert!(renderer.reload_shader(Shader::Sky, &source).is_err());
+
+    // A missing entry point
+    let source = shader_source(Shader::Block).replace("fs_cutout", "fs_renamed");
+    assert!(renderer.reload_shader(Shader::Block, &source).is_err());
+
+    // An input no vertex buffer provides
+    let source = shader_source(Shader::Block).replace(
+        "@location(12) uv_rect: vec4<f32>,",
+        "@location(12) uv_rect: vec4<f32>,\n    @location(13) extra: f32,",
+    );
+    let error = renderer.reload_shader(Shader::Block, &source).unwrap_err();
+    assert!(format!("{:#}", error).contains("@location(13)"));
+
+    // Everything still draws with the pipelines from before
+    let world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+    renderer.render(&world, &target.view);
+    target.read_image(&renderer).unwrap();
+}
+
+#[test]
+fn watcher_reports_changed_files() {
+    let dir = common::TempDir::new("shader-watcher");
+    let path = dir.join(Shader::Sky.file_name());
+    std::fs::write(&path, "old").unwrap();
+
+    let mut watcher = ShaderWatcher::new(dir.to_path_buf());
+    std::thread::sleep(Duration::from_millis(600));
+    assert!(watcher.changed().is_empty());
+
+    std::fs::write(&path, "new").unwrap();
+    // Some file systems only store whole seconds
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(2))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(600));
+    let changed = watcher.changed();
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].0, Shader::Sky);
+    assert_eq!(changed[0].1.as_ref().unwrap(), "new");
+}
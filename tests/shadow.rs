@@ -0,0 +1,76 @@
+//! Cascade splits and the texel snapping of the cascade projections, all on the CPU.
+
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Rad, Vector2, Vector3};
+use tutorial12_camera::shadow::{cascade_matrix, cascade_splits, CASCADE_COUNT};
+
+#[test]
+fn splits_grow_and_end_at_the_far_plane() {
+    for lambda in [0.0, 0.5, 0.75, 1.0] {
+        let splits = cascade_splits(0.1, 200.0, CASCADE_COUNT, lambda);
+        assert_eq!(splits.len(), CASCADE_COUNT);
+        assert!(splits[0] > 0.1);
+        assert!(
+            splits.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            splits
+        );
+        assert!(
+            (splits[CASCADE_COUNT - 1] - 200.0).abs() < 1e-3,
+            "{:?}",
+            splits
+        );
+    }
+    // Uniform splits are evenly spaced
+    let splits = cascade_splits(1.0, 101.0, 4, 0.0);
+    assert_eq!(splits, [26.0, 51.0, 76.0, 101.0]);
+}
+
+// Position of `point` in the texels of a `map_size` shadow map
+fn texel_position(matrix: Matrix4<f32>, point: Vector3<f32>, map_size: u32) -> (f32, f32) {
+    let clip = matrix * point.extend(1.0);
+    let scale = map_size as f32 / 2.0;
+    (clip.x / clip.w * scale, clip.y / clip.w * scale)
+}
+
+#[test]
+fn cascades_are_snapped_to_whole_texels() {
+    let sun = Vector3::new(-0.3, -1.0, 0.4).normalize();
+    let map_size = 2048;
+    let point = Vector3::new(3.3, 1.7, -7.1);
+    let mut fractions = Vec::new();
+    for step in 0..8 {
+        // Moving and turning the camera by amounts that do not line up with the texels
+        let eye = Point3::new(
+            step as f32 * 0.37,
+            4.0 + step as f32 * 0.11,
+            step as f32 * -0.53,
+        );
+        let direction = Vector3::new((step as f32 * 0.4).cos(), -0.2, (step as f32 * 0.4).sin());
+        let view = Matrix4::look_to_rh(eye, direction, Vector3::unit_y());
+        let matrix = cascade_matrix(
+            view,
+            Rad::from(Deg(45.0)),
+            16.0 / 9.0,
+            0.1..20.0,
+            sun,
+            100.0,
+            map_size,
+        );
+
+        // The world origin always lands on a texel corner
+        let (x, y) = texel_position(matrix, Vector3::new(0.0, 0.0, 0.0), map_size);
+        assert!((x - x.round()).abs() < 1e-2, "{} is not a whole texel", x);
+        assert!((y - y.round()).abs() < 1e-2, "{} is not a whole texel", y);
+
+        // So every other point keeps its place inside its texel
+        let (x, y) = texel_position(matrix, point, map_size);
+        fractions.push(Vector2::new(x - x.floor(), y - y.floor()));
+    }
+    for fraction in &fractions {
+        assert!(
+            (fraction - fractions[0]).magnitude() < 2e-2,
+            "{:?}",
+            fractions
+        );
+    }
+}
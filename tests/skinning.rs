@@ -0,0 +1,224 @@
+//! Skeletons and clips are plain data, the last two tests load `arm.glb`: an arm with a
+//! root and an elbow joint standing on a plate, with a "wave" and an "idle" animation.
+
+mod common;
+
+use cgmath::{
+    Deg, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix, Vector3, Vector4,
+};
+use std::time::Duration;
+use tutorial12_camera::{
+    entity::Entity,
+    offscreen::OffscreenTarget,
+    skeleton::{
+        AnimationClip, AnimationPlayer, Channel, Interpolation, Joint, Keyframes, Skeleton,
+        Transform, MAX_JOINTS,
+    },
+    world::World,
+};
+
+fn joint(name: &str, parent: Option<usize>, height: f32) -> Joint {
+    Joint {
+        name: name.to_string(),
+        parent,
+        rest: Transform {
+            translation: Vector3::new(0.0, height, 0.0),
+            ..Default::default()
+        },
+        inverse_bind: Matrix4::from_translation(Vector3::new(0.0, -1.0, 0.0) * height.abs()),
+    }
+}
+
+/// An arm with the elbow one up from the root, listed before its parent.
+fn arm() -> Skeleton {
+    Skeleton::new(
+        vec![joint("elbow", Some(1), 1.0), joint("root", None, 0.0)],
+        Matrix4::identity(),
+    )
+    .unwrap()
+}
+
+fn bent(degrees: f32) -> Transform {
+    Transform {
+        translation: Vector3::new(0.0, 1.0, 0.0),
+        rotation: Quaternion::from_angle_z(Deg(degrees)),
+        ..Default::default()
+    }
+}
+
+fn close(a: Vector4<f32>, b: Vector4<f32>) -> bool {
+    (a - b).magnitude() < 1e-5
+}
+
+#[test]
+fn joint_matrices_follow_the_pose() {
+    let skeleton = arm();
+    for matrix in skeleton.joint_matrices(&skeleton.rest_pose()) {
+        assert!(matrix.eq(&Matrix4::identity()));
+    }
+
+    let mut pose = skeleton.rest_pose();
+    pose[skeleton.joint("elbow").unwrap()] = bent(90.0);
+    let matrices = skeleton.joint_matrices(&pose);
+    let hand = Vector4::new(0.0, 2.0, 0.0, 1.0);
+    assert!(close(matrices[0] * hand, Vector4::new(-1.0, 1.0, 0.0, 1.0)));
+    assert!(close(matrices[1] * hand, hand));
+
+    // Lifting the root carries the elbow along
+    pose[1].translation.y = 3.0;
+    let matrices = skeleton.joint_matrices(&pose);
+    assert!(close(matrices[0] * hand, Vector4::new(-1.0, 4.0, 0.0, 1.0)));
+
+    let cycle = vec![joint("a", Some(1), 1.0), joint("b", Some(0), 1.0)];
+    assert!(Skeleton::new(cycle, Matrix4::identity()).is_err());
+    let too_many = (0..=MAX_JOINTS).map(|_| joint("bone", None, 0.0)).collect();
+    assert!(Skeleton::new(too_many, Matrix4::identity()).is_err());
+}
+
+#[test]
+fn clips_blend_between_keyframes() {
+    let clip = AnimationClip {
+        name: "wave".to_string(),
+        duration: 2.0,
+        channels: vec![
+            Channel {
+                joint: 0,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 1.0],
+                keyframes: Keyframes::Rotation(vec![
+                    Quaternion::from_angle_z(Deg(0.0)),
+                    Quaternion::from_angle_z(Deg(90.0)),
+                ]),
+            },
+            Channel {
+                joint: 1,
+                interpolation: Interpolation::Step,
+                times: vec![0.0, 1.0, 2.0],
+                keyframes: Keyframes::Translation(vec![
+                    Vector3::new(0.0, 0.0, 0.0),
+                    Vector3::new(0.0, 1.0, 0.0),
+                    Vector3::new(0.0, 2.0, 0.0),
+                ]),
+            },
+        ],
+    };
+    let sample = |time| {
+        let mut pose = vec![Transform::default(); 2];
+        clip.sample(time, &mut pose);
+        pose
+    };
+    let half = sample(0.5);
+    let expected = Quaternion::from_angle_z(Deg(45.0));
+    assert!((half[0].rotation - expected).magnitude() < 1e-5);
+    assert_eq!(half[1].translation.y, 0.0);
+    assert_eq!(sample(1.5)[1].translation.y, 1.0);
+    // Past the last keyframe the pose holds
+    let after = sample(5.0);
+    assert!((after[0].rotation - Quaternion::from_angle_z(Deg(90.0))).magnitude() < 1e-5);
+    assert_eq!(after[1].translation.y, 2.0);
+
+    let mut looping = AnimationPlayer::new(0);
+    looping.advance(2.5, clip.duration);
+    assert_eq!(looping.time, 0.5);
+    let mut once = AnimationPlayer {
+        looping: false,
+        speed: -1.0,
+        ..AnimationPlayer::new(0)
+    };
+    once.advance(1.0, clip.duration);
+    assert_eq!(once.time, 0.0);
+}
+
+#[tokio::test]
+async fn gltf_models_load_with_their_skeleton() {
+    let renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let arm = world.models.load("arm.glb", &renderer).await.unwrap();
+
+    // The skinned arm and the plate with a colour instead of a texture
+    let model = world.models.get(arm).unwrap();
+    assert_eq!(model.meshes.len(), 2);
+    assert_eq!(model.materials.len(), 2);
+    assert_eq!(
+        model
+            .meshes
+            .iter()
+            .filter(|m| m.skin_buffer.is_some())
+            .count(),
+        1
+    );
+
+    let skeleton = world.models.skeleton(arm).unwrap();
+    let names: Vec<_> = skeleton.joints.iter().map(|j| j.name.as_str()).collect();
+    assert_eq!(names, ["root", "elbow"]);
+    assert_eq!(skeleton.joints[1].parent, Some(0));
+    let clips: Vec<_> = world
+        .models
+        .animations(arm)
+        .iter()
+        .map(|clip| (clip.name.as_str(), clip.duration))
+        .collect();
+    assert_eq!(clips, [("wave", 1.0), ("idle", 1.0)]);
+
+    let wave = world.models.animation(arm, "wave").unwrap();
+    let mut entity = Entity::new(arm, Vector3::new(0.0, 0.0, 0.0));
+    for matrix in world.models.joint_matrices(&entity).unwrap() {
+        assert!(matrix.eq(&Matrix4::identity()));
+    }
+    entity.animation = Some(AnimationPlayer {
+        time: 0.5,
+        ..AnimationPlayer::new(wave)
+    });
+    let matrices = world.models.joint_matrices(&entity).unwrap();
+    let hand = Vector4::new(0.0, 2.0, 0.0, 1.0);
+    assert!(close(matrices[1] * hand, Vector4::new(-1.0, 1.0, 0.0, 1.0)));
+
+    let cube = world.models.load("cube.obj", &renderer).await.unwrap();
+    assert!(world.models.skeleton(cube).is_none());
+    assert!(world
+        .models
+        .joint_matrices(&Entity::new(cube, Vector3::new(0.0, 0.0, 0.0)))
+        .is_none());
+}
+
+#[tokio::test]
+async fn skinned_entities_move_with_their_animation() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+    let arm = world.models.load("arm.glb", &renderer).await.unwrap();
+    let wave = world.models.animation(arm, "wave").unwrap();
+
+    renderer.update(
+        &Point3::new(10.0, 3.0, 16.0),
+        Rad(-0.2),
+        Rad::from(Deg(-90.0)),
+    );
+    let before = renderer.render(&world, &target.view);
+    for x in [8.0, 12.0] {
+        let mut entity = Entity::new(arm, Vector3::new(x, 1.0, 10.0));
+        entity.animation = Some(AnimationPlayer::new(wave));
+        world.entities.spawn(entity);
+    }
+    let after = renderer.render(&world, &target.view);
+    assert_eq!(after.entities, 2);
+    // 18 triangles of arm and 2 of plate, drawn into every shadow cascade and the main pass
+    let cascades = 4;
+    assert_eq!(after.triangles - before.triangles, 2 * 20 * (cascades + 1));
+    // The arms are drawn one at a time, the plates together
+    assert_eq!(
+        after.draw_calls - before.draw_calls,
+        3 * (cascades as u32 + 1)
+    );
+    renderer.render(&world, &target.view);
+    let rest = target.read_image(&renderer).unwrap();
+
+    world.update(&renderer.queue, Duration::from_millis(500));
+    renderer.render(&world, &target.view);
+    assert_ne!(target.read_image(&renderer).unwrap(), rest);
+
+    // A full loop brings the arms back to where they started
+    world.update(&renderer.queue, Duration::from_millis(500));
+    renderer.render(&world, &target.view);
+    assert_eq!(target.read_image(&renderer).unwrap(), rest);
+}
@@ -0,0 +1,68 @@
+//! The sun, moon and sky colours over the course of a day, all on the CPU.
+
+use cgmath::{InnerSpace, Vector3};
+use std::time::Duration;
+use tutorial12_camera::sky::TimeOfDay;
+
+const MIDNIGHT: f32 = 0.0;
+const SUNRISE: f32 = 0.25;
+const NOON: f32 = 0.5;
+const SUNSET: f32 = 0.75;
+
+fn at(time: f32) -> TimeOfDay {
+    TimeOfDay::new(time, 60.0)
+}
+
+#[test]
+fn time_wraps_around_at_midnight() {
+    let mut time = TimeOfDay::new(0.9, 10.0);
+    time.advance(Duration::from_secs(2));
+    assert!((time.time - 0.1).abs() < 1e-5, "{}", time.time);
+}
+
+#[test]
+fn sun_rises_and_sets() {
+    assert!(at(NOON).sun_direction().y > 0.9);
+    assert!(at(MIDNIGHT).sun_direction().y < -0.9);
+    for time in [SUNRISE, SUNSET] {
+        assert!(at(time).sun_direction().y.abs() < 1e-5);
+    }
+    assert!((at(0.3).sun_direction().magnitude() - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn light_fades_between_sun_and_moon() {
+    let sun_color = Vector3::new(1.0, 0.95, 0.85);
+    let moon_color = Vector3::new(0.15, 0.17, 0.25);
+    let (direction, color) = at(NOON).light();
+    assert!((direction + at(NOON).sun_direction()).magnitude() < 1e-5);
+    assert!((color - sun_color).magnitude() < 1e-5);
+    let (_, color) = at(MIDNIGHT).light();
+    assert!((color - moon_color).magnitude() < 1e-5);
+
+    // Dark while the light switches over at the horizon, without jumps on either side
+    for time in [SUNRISE, SUNSET] {
+        assert!(at(time).light().1.magnitude() < 1e-4);
+    }
+    let mut previous = at(0.0).light().1;
+    for step in 1..=10000 {
+        let (direction, color) = at(step as f32 / 10000.0).light();
+        // Sun and moon both shine down onto the world
+        assert!(direction.y <= 0.0);
+        assert!((color - previous).magnitude() < 0.02, "jump at {}", step);
+        previous = color;
+    }
+}
+
+#[test]
+fn sky_is_blue_by_day_and_red_at_sunset() {
+    let (noon_zenith, noon_horizon) = at(NOON).sky_colors();
+    let (night_zenith, _) = at(MIDNIGHT).sky_colors();
+    assert!(noon_zenith.z > noon_zenith.x);
+    assert!(noon_zenith.magnitude() > 10.0 * night_zenith.magnitude());
+
+    let (_, sunset_horizon) = at(SUNSET).sky_colors();
+    assert!(sunset_horizon.x > sunset_horizon.z);
+    assert!(noon_horizon.z > noon_horizon.x);
+    assert_eq!(at(SUNSET).fog_color(), sunset_horizon);
+}
@@ -0,0 +1,127 @@
+//! A temporary resource pack with one broken file for every kind of problem, next to the
+//! assets built into the binary which all have to pass.
+
+mod common;
+
+use tutorial12_camera::{
+    resources::{self, AssetSources, ModelError},
+    validate,
+    world::World,
+};
+
+const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n";
+
+#[tokio::test]
+async fn broken_assets_fail_with_what_is_wrong() {
+    let pack = common::TempDir::new("validate");
+    std::fs::create_dir_all(pack.join("shaders")).unwrap();
+    let files = [
+        ("plain.obj", TRIANGLE.to_string()),
+        (
+            "no_uvs.obj",
+            format!("mtllib cube.mtl\nusemtl Material\n{}", TRIANGLE),
+        ),
+        (
+            "no_normals.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n".to_string(),
+        ),
+        (
+            "bad_material.obj",
+            format!("mtllib cube.mtl\nusemtl Marble\n{}", TRIANGLE),
+        ),
+        (
+            "lost.obj",
+            format!("mtllib lost.mtl\nusemtl Material\n{}", TRIANGLE),
+        ),
+        ("model.fbx", String::new()),
+        ("triangle.gltf", common::triangle_gltf(&[0, 1, 2], None)),
+        ("skinned.gltf", common::triangle_gltf(&[0, 1, 2], Some(0))),
+        (
+            "partial_triangle.gltf",
+            common::triangle_gltf(&[0, 1, 2, 0], None),
+        ),
+        ("far_vertex.gltf", common::triangle_gltf(&[0, 1, 3], None)),
+        ("bad_joint.gltf", common::triangle_gltf(&[0, 1, 2], Some(1))),
+        ("stone.mtl", "newmtl Stone\nmap_Kd marble.png\n".to_string()),
+        ("broken.png", "not a PNG".to_string()),
+        ("shaders/water.wgsl", String::new()),
+    ];
+    for (name, contents) in &files {
+        std::fs::write(pack.join(name), contents).unwrap();
+    }
+    resources::set_asset_sources(AssetSources {
+        root: None,
+        packs: vec![pack.to_path_buf()],
+    });
+
+    let renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    // Meshes without a material are drawn in white
+    let plain = world.models.load("plain.obj", &renderer).await.unwrap();
+    assert_eq!(world.models.get(plain).unwrap().materials.len(), 1);
+    for name in ["triangle.gltf", "skinned.gltf"] {
+        world.models.load(name, &renderer).await.unwrap();
+    }
+
+    let mut errors = Vec::new();
+    for name in [
+        "no_uvs.obj",
+        "no_normals.obj",
+        "bad_material.obj",
+        "lost.obj",
+        "model.fbx",
+        "missing.obj",
+        "partial_triangle.gltf",
+        "far_vertex.gltf",
+        "bad_joint.gltf",
+    ] {
+        let error = world.models.load(name, &renderer).await.unwrap_err();
+        let error = error.downcast::<ModelError>().unwrap();
+        assert_eq!(error.model(), name);
+        errors.push(error);
+    }
+    assert!(
+        matches!(&errors[0], ModelError::MissingTexCoords { mesh, .. } if mesh == "unnamed_object")
+    );
+    assert!(matches!(errors[1], ModelError::MissingNormals { .. }));
+    assert!(matches!(&errors[2], ModelError::BadMaterial { material, .. } if material == "Marble"));
+    assert!(matches!(&errors[3], ModelError::MissingFile { file, .. } if file == "lost.mtl"));
+    assert!(matches!(errors[4], ModelError::Unsupported { .. }));
+    assert_eq!(
+        errors[5].to_string(),
+        "There is no asset called missing.obj"
+    );
+    // Broken index and joint buffers are caught before anything reads past their ends
+    for (error, problem) in errors[6..].iter().zip([
+        "not a whole number of triangles",
+        "refers to vertex 3, but only has 3",
+        "refers to joint 1, but the skin only has 1",
+    ]) {
+        assert!(matches!(error, ModelError::Invalid { .. }));
+        assert!(format!("{:#}", error).contains(problem), "{:#}", error);
+    }
+
+    // Every problem at once, the built in assets have none
+    let report = validate::validate_assets(&renderer).await.unwrap();
+    let problems: Vec<_> = report.problems.iter().map(|p| p.file.as_str()).collect();
+    assert_eq!(
+        problems,
+        [
+            "bad_joint.gltf",
+            "bad_material.obj",
+            "broken.png",
+            "far_vertex.gltf",
+            "lost.obj",
+            "no_normals.obj",
+            "no_uvs.obj",
+            "partial_triangle.gltf",
+            "shaders/water.wgsl",
+            "stone.mtl",
+        ]
+    );
+    assert!(report.checked.iter().any(|file| file == "arm.glb"));
+    assert!(report.checked.iter().any(|file| file == "plain.obj"));
+    assert!(!report.checked.iter().any(|file| file == "model.fbx"));
+
+    resources::set_asset_sources(AssetSources::default());
+}
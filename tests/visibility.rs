@@ -0,0 +1,118 @@
+//! Face connections and the flood fill are plain data, only the last test renders.
+
+mod common;
+
+use cgmath::{Point3, Rad, Vector3};
+use std::collections::HashMap;
+use tutorial12_camera::{
+    block::{BlockFace, BlockKind},
+    offscreen::OffscreenTarget,
+    visibility::{face_connections, visible_sections, FaceConnections, SECTION_BLOCKS},
+    world::World,
+};
+
+fn cells() -> impl Iterator<Item = Vector3<i32>> {
+    (0..SECTION_BLOCKS).flat_map(|x| {
+        (0..SECTION_BLOCKS)
+            .flat_map(move |y| (0..SECTION_BLOCKS).map(move |z| Vector3::new(x, y, z)))
+    })
+}
+
+#[test]
+fn empty_and_solid_sections() {
+    assert_eq!(face_connections([]), FaceConnections::ALL);
+
+    let solid = face_connections(cells());
+    for a in BlockFace::ALL {
+        for b in BlockFace::ALL {
+            assert!(!solid.connects(a, b));
+        }
+    }
+}
+
+#[test]
+fn a_wall_splits_a_section() {
+    let wall = face_connections(cells().filter(|cell| cell.x == 8));
+    assert!(!wall.connects(BlockFace::West, BlockFace::East));
+    assert!(wall.connects(BlockFace::West, BlockFace::Top));
+    assert!(wall.connects(BlockFace::East, BlockFace::North));
+    assert!(wall.connects(BlockFace::Top, BlockFace::Bottom));
+    assert!(wall.connects(BlockFace::North, BlockFace::South));
+}
+
+#[test]
+fn a_tunnel_connects_its_ends() {
+    // Solid rock with a tunnel one block wide running along z
+    let tunnel = face_connections(cells().filter(|cell| (cell.x, cell.y) != (5, 5)));
+    assert!(tunnel.connects(BlockFace::North, BlockFace::South));
+    assert!(tunnel.connects(BlockFace::South, BlockFace::North));
+    assert!(!tunnel.connects(BlockFace::North, BlockFace::Top));
+    assert!(!tunnel.connects(BlockFace::East, BlockFace::West));
+}
+
+#[test]
+fn flood_fill_stops_at_closed_sections() {
+    // A layer of solid rock at y = -1 with a cave below it
+    let mut sections = HashMap::new();
+    for x in -2..=2 {
+        for z in -2..=2 {
+            sections.insert(Vector3::new(x, -1, z), FaceConnections::default());
+        }
+    }
+    let connections = |s: Vector3<i32>| sections.get(&s).copied().unwrap_or(FaceConnections::ALL);
+    let bounds = (Vector3::new(-2, -3, -2), Vector3::new(2, 1, 2));
+
+    let from_above = visible_sections(Vector3::new(0, 1, 0), bounds, connections);
+    // The top of the rock can be seen, the cave cannot
+    assert!(from_above.contains(&Vector3::new(0, 0, 0)));
+    assert!(from_above.contains(&Vector3::new(2, -1, 2)));
+    assert!(!from_above.contains(&Vector3::new(0, -2, 0)));
+
+    let from_cave = visible_sections(Vector3::new(0, -2, 0), bounds, connections);
+    assert!(from_cave.contains(&Vector3::new(2, -3, -2)));
+    assert!(from_cave.contains(&Vector3::new(0, -1, 0)));
+    assert!(!from_cave.contains(&Vector3::new(0, 0, 0)));
+}
+
+#[test]
+fn flood_fill_does_not_turn_back() {
+    // A wall in every section along x = 1 only lets the fill past through y = 2
+    let connections = |s: Vector3<i32>| {
+        if s.x == 1 && s.y < 2 {
+            FaceConnections::default()
+        } else {
+            FaceConnections::ALL
+        }
+    };
+    let bounds = (Vector3::new(0, 0, 0), Vector3::new(2, 2, 0));
+
+    let visible = visible_sections(Vector3::new(0, 0, 0), bounds, connections);
+    // Going up, across and back down would be needed to see behind the wall
+    assert!(visible.contains(&Vector3::new(2, 2, 0)));
+    assert!(!visible.contains(&Vector3::new(2, 0, 0)));
+}
+
+#[tokio::test]
+async fn caves_under_the_floor_are_not_drawn() {
+    let mut renderer = common::headless_renderer(64, 64).await;
+    let mut world = World::new(&renderer).await;
+    let target = OffscreenTarget::new(&renderer);
+    // A small room deep below the middle of the floor
+    let mut cave_blocks = 0;
+    for x in 0..4 {
+        for z in 0..4 {
+            world.place(Vector3::new(96 + x * 2, -40, 96 + z * 2), BlockKind::Cobble);
+            cave_blocks += 1;
+        }
+    }
+
+    let mut render = |position: Point3<f32>| {
+        renderer.update(&position, Rad(-0.5), Rad(0.8));
+        renderer.render(&world, &target.view)
+    };
+    assert_eq!(
+        render(Point3::new(100.0, 40.0, 100.0)).occluded,
+        cave_blocks
+    );
+    assert_eq!(render(Point3::new(100.0, -36.0, 100.0)).occluded, 0);
+}